@@ -16,20 +16,25 @@ use block_engine_wgpu::{
     camera, 
     world, 
     GameData, 
-    GameEvent, GameWindow  
+    GameEvent, GameWindow,
+    EngineError
 };
 
 fn game_init(data: GameData) {
+    // The controller's speeds are tuned for the original physics model
+    data.world.set_physics_config(world::PhysicsConfig::legacy());
+
     terrain::generate(data.world);
 
     data.world.add_tile( {
         let mut pl = tile::Cube::new(
             (1, 1, 1).into(), [1.0; 3]);
         world::Drawable::set_light(&mut pl, [1.0; 4]);
+        pl.casts_shadows = true;
         pl
     } );
 
-    data.world.add_entity_with_tag(
+    let player = data.world.add_entity_with_tag(
         "player",
         entity::PlaceholderEntity {
             center: (0.0, 6.0, 0.0).into(),
@@ -46,11 +51,16 @@ fn game_init(data: GameData) {
         .pitch(1.0)
         .yaw(0.1)
         .target([0.0; 3].into())
+        .follow(player, Vector3::zero())
         .build();
 } 
 
-fn main() {
-    let config = Config { fps: 60 };
+fn main() -> Result<(), EngineError> {
+    let config = Config {
+        title: String::from("Scavenger"),
+        msaa_samples: 4,
+        ..Config::default()
+    };
 
     let controller = Arc::new(Mutex::new(controller::PlayerController {
         direction: 0,
@@ -63,31 +73,28 @@ fn main() {
         let controller_ref = Arc::clone(&controller);
 
         move |data: GameData| {
-            let center = data.world.get_entity("player").unwrap().borrow().center();
-            data.camera.set_target((center.x, center.y.round(), center.z).into());
-
-            let mut handle = data.world.get_entity("player").unwrap();
-            let mut entity = handle.borrow_mut();
-
-            {
-                let mut velocity = entity.velocity();
-                controller_ref.lock().unwrap().aggregate_player_velocity(&mut velocity);
-                entity.set_velocity(velocity);
-
-                if let Some(mut drag_vector) = controller_ref.lock().unwrap().spawn_projectile() {
-                    drag_vector *= -1.0;
-
-                    let entity = entity::PlaceholderEntity {
-                        center: entity.center(),
-                        color: [1.0; 3],
-                        light: Some([1.0, 1.0, 1.0, 0.2]),
-                        velocity: drag_vector,
-                        collisions: (false, false, false).into(),
-                        weight: 0.05,
-                    };
-
-                    data.world.add_entity(entity, Some(Duration::from_secs(4)));
-                }
+            let handle = data.world.get_entity("player").unwrap();
+            let entity = data.world.entity_mut(handle).unwrap();
+
+            let center = entity.center();
+
+            let mut velocity = entity.velocity();
+            controller_ref.lock().unwrap().aggregate_player_velocity(&mut velocity);
+            entity.set_velocity(velocity);
+
+            if let Some(mut drag_vector) = controller_ref.lock().unwrap().spawn_projectile() {
+                drag_vector *= -1.0;
+
+                let entity = entity::PlaceholderEntity {
+                    center,
+                    color: [1.0; 3],
+                    light: Some([1.0, 1.0, 1.0, 0.2]),
+                    velocity: drag_vector,
+                    collisions: (false, false, false).into(),
+                    weight: 0.05,
+                };
+
+                data.world.add_entity(entity, Some(Duration::from_secs(4)));
             }
         }
     };
@@ -101,7 +108,7 @@ fn main() {
         }
     };
 
-    pollster::block_on(run(config, game_init, game_update, process_events)); 
+    pollster::block_on(run(config, game_init, game_update, process_events))
 }
 
 /*
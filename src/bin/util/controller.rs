@@ -46,7 +46,7 @@ impl PlayerController {
         match &event {    
             // Zoom
             GameEvent::MouseWheel { delta } => {
-                let scroll_amount = -1.0 * match delta {
+                let scroll_amount = -match delta {
                     // A mouse line is about 1 px.
                     event::MouseScrollDelta::LineDelta(_, scroll) => 
                         scroll * 1.0,
@@ -5,7 +5,8 @@ pub struct Cube {
     pub(crate) position: Point3<i16>,
     pub(crate) hw: f32,
     pub(crate) color: [f32; 3],
-    pub(crate) light: Option<[f32; 4]>
+    pub(crate) light: Option<[f32; 4]>,
+    pub(crate) casts_shadows: bool
 }
 
 impl Default for Cube {
@@ -14,7 +15,8 @@ impl Default for Cube {
             position: [0, 0, 0].into(), 
             hw: 0.5,
             color: [0.3, 0.3, 0.8],
-            light: None
+            light: None,
+            casts_shadows: false
         }
     }
 }
@@ -30,7 +32,7 @@ impl Cube {
 
 impl Cube {
     pub fn new(position: Point3<i16>, color: [f32; 3]) -> Self {
-        Self { position, hw: 0.5, color, light: None }
+        Self { position, hw: 0.5, color, light: None, casts_shadows: false }
     }
 }
 
@@ -38,6 +40,10 @@ impl world::Tile for Cube {
     fn position(&self) -> Point3<i16> { self.position }
 
     fn set_position(&mut self, position: Point3<i16>) { self.position = position; }
+
+    // Emissive cubes are lit from the inside (their normals are flipped),
+    // so they continue to provide their own geometry
+    fn is_opaque_cube(&self) -> bool { self.light.is_none() && self.hw == 0.5 }
 }
 
 impl world::Drawable for Cube {
@@ -50,6 +56,8 @@ impl world::Drawable for Cube {
     fn light(&self) -> Option<[f32; 4]> { self.light }
     fn set_light(&mut self, light: [f32; 4]) { self.light = Some(light); }
 
+    fn casts_shadows(&self) -> bool { self.casts_shadows }
+
     fn build_object_data(&self) -> world::Triangles {
         let center = Point3::new(
             self.position.x as f32, 
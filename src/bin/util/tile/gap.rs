@@ -26,6 +26,8 @@ impl Gap {
 impl world::Tile for Gap { 
     fn position(&self) -> Point3<i16> { self.position }
     fn set_position(&mut self, position: Point3<i16>) { self.position = position; }
+
+    fn collision(&self) -> world::TileCollision { world::TileCollision::Passable }
 }
 
 impl world::Drawable for Gap {
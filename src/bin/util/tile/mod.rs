@@ -2,4 +2,5 @@ mod cube;
 pub use cube::Cube;
 
 mod gap;
+#[allow(unused_imports)]
 pub use gap::Gap;
\ No newline at end of file
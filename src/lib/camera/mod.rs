@@ -3,29 +3,61 @@ use cgmath::{
     Matrix4, 
     SquareMatrix, 
     EuclideanSpace, 
-    Vector3
+    InnerSpace,
+    Vector3,
+    Vector4
 };
 
+use crate::world;
+
+// How the eye is placed relative to the Camera's target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    // The eye circles the target, see set_distance, set_pitch and set_yaw
+    Orbit,
+    // The eye is at the target, and looks in the direction given by the pitch and yaw
+    FirstPerson,
+    // Like FirstPerson, but the view can be rolled (see set_roll),
+    // and move_relative follows the view instead of staying level
+    FreeFly,
+    // The eye stays at the given point and looks at the target
+    LookAt { eye: Point3<f32> }
+}
+
+#[derive(Clone)]
 pub struct Camera {
+    pub(crate) mode: CameraMode,
     pub(crate) distance: f32,
     pub(crate) eye: Point3<f32>,
     pub(crate) target: Point3<f32>,
+    // The direction the eye faces, and which way is up on the screen
+    pub(crate) forward: Vector3<f32>,
+    pub(crate) up: Vector3<f32>,
     pub(crate) pitch: f32,
     pub(crate) yaw: f32,
+    // Only used by CameraMode::FreeFly
+    pub(crate) roll: f32,
     pub(crate) aspect: f32,
-    pub(crate) bounds: CameraBounds
+    pub(crate) bounds: CameraBounds,
+    // The entity (or light) that the target is attached to, and the target's offset from it
+    pub(crate) follow: Option<(world::EntityHandle, Vector3<f32>)>
 }
 
 impl Default for Camera {
     fn default() -> Self {
         let mut camera = Self {
+            mode: CameraMode::Orbit,
             distance: 10.0,
             eye: [0.0, 0.0, 0.0].into(),
             target: [0.0, 0.0, 0.0].into(),
+            forward: -Vector3::unit_z(),
+            up: Vector3::unit_y(),
             pitch: 1.5,
             yaw: 1.25,
+            roll: 0.0,
             aspect: 1.0,
-            bounds: CameraBounds::default()
+            bounds: CameraBounds::default(),
+            follow: None
         };
 
         camera.update();
@@ -35,13 +67,87 @@ impl Default for Camera {
 
 impl Camera {
     pub(crate) fn update(&mut self) {
-        self.eye = Point3::new(
-            self.distance * self.yaw.sin() * self.pitch.cos(),
-            self.distance * self.pitch.sin(),
-            self.distance * self.yaw.cos() * self.pitch.cos()
+        // Points from the target to the eye when orbiting, and the other way otherwise
+        let direction = Vector3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos()
         );
 
-        self.eye += self.target.to_vec();
+        self.up = Vector3::unit_y();
+
+        match self.mode {
+            CameraMode::Orbit => {
+                self.eye = Point3::new(
+                    self.distance * self.yaw.sin() * self.pitch.cos(),
+                    self.distance * self.pitch.sin(),
+                    self.distance * self.yaw.cos() * self.pitch.cos()
+                );
+
+                self.eye += self.target.to_vec();
+                self.forward = self.target - self.eye;
+            },
+            CameraMode::FirstPerson => {
+                self.eye = self.target;
+                self.forward = -direction;
+            },
+            CameraMode::FreeFly => {
+                self.eye = self.target;
+                self.forward = -direction;
+            },
+            CameraMode::LookAt { eye } => {
+                self.eye = eye;
+
+                // The previous direction is kept when the eye is at the target
+                let forward = self.target - self.eye;
+                if forward.magnitude2() > 0.0 {
+                    self.forward = forward;
+                }
+            }
+        }
+
+        // Y can't be up while looking straight up or down
+        let right = self.level_right();
+        match self.mode {
+            CameraMode::FreeFly => {
+                // Positive rolls bank to the right
+                let right = right.unwrap_or_else(|| self.yaw_right());
+                let up = right.cross(self.forward.normalize());
+                self.up = up * self.roll.cos() + right * self.roll.sin();
+            },
+            _ if right.is_none() => self.up = self.yaw_right().cross(self.forward.normalize()),
+            _ => {  }
+        }
+    }
+
+    // The right side of the screen while Y is up, None if the eye faces straight up or down
+    fn level_right(&self) -> Option<Vector3<f32>> {
+        let right = self.forward.normalize().cross(Vector3::unit_y());
+        (right.magnitude2() > 1e-6).then(|| right.normalize())
+    }
+
+    // Used when there's no level_right, so that the yaw still turns the view (even in LookAt mode)
+    fn yaw_right(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.cos(), 0.0, -self.yaw.sin())
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    // The target is kept, so switching from Orbit to FirstPerson moves the eye to the target
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+        self.update();
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    // The normalized direction that the eye is facing
+    pub fn direction(&self) -> Vector3<f32> {
+        self.forward.normalize()
     }
 
     pub fn set_distance(&mut self, distance: f32) {
@@ -81,6 +187,16 @@ impl Camera {
         self.set_yaw(self.yaw + delta);
     }
 
+    // In radians, only used by CameraMode::FreeFly
+    pub fn set_roll(&mut self, roll: f32) {
+        self.roll = roll;
+        self.update();
+    }
+
+    pub fn add_roll(&mut self, delta: f32) {
+        self.set_roll(self.roll + delta);
+    }
+
     pub fn set_target(&mut self, target: Point3<f32>) {
         self.target = target;
         self.update();
@@ -90,6 +206,50 @@ impl Camera {
         self.set_target(self.target + displacement);
         self.update();
     }
+
+    // Moves the target relative to the way the Camera is facing.
+    // Movement stays level (up is always along the Y axis), except in CameraMode::FreeFly
+    pub fn move_relative(&mut self, forward: f32, right: f32, up: f32) {
+        let (forward_axis, right_axis, up_axis) = match self.mode {
+            CameraMode::FreeFly => {
+                let forward_axis = self.forward.normalize();
+                (forward_axis, forward_axis.cross(self.up).normalize(), self.up)
+            },
+            _ => {
+                let forward_axis = Vector3::new(-self.yaw.sin(), 0.0, -self.yaw.cos());
+                (forward_axis, forward_axis.cross(Vector3::unit_y()), Vector3::unit_y())
+            }
+        };
+
+        self.displace_target(forward_axis * forward + right_axis * right + up_axis * up);
+    }
+
+    // Moves the target along with the entity after every tick, until unfollow is called
+    // or the entity is despawned
+    pub fn follow(&mut self, entity: world::EntityHandle, offset: Vector3<f32>) {
+        self.follow = Some((entity, offset));
+    }
+
+    pub fn unfollow(&mut self) {
+        self.follow = None;
+    }
+
+    pub(crate) fn follow_entity(&mut self, world: &world::World) {
+        if let Some((entity, offset)) = self.follow {
+            match world.position(entity) {
+                Some(position) => self.set_target(position + offset),
+                None => self.follow = None
+            }
+        }
+    }
+
+    // A copy of the Camera with its target `alpha` of the way from the previous target to the current one
+    pub(crate) fn interpolate(&self, previous_target: Point3<f32>, alpha: f32) -> Self {
+        let mut camera = self.clone();
+        camera.target = previous_target + (self.target - previous_target) * alpha;
+        camera.update();
+        camera
+    }
 }
 
 impl Camera {
@@ -97,18 +257,18 @@ impl Camera {
     const ZNEAR: f32 = 0.1;
     const ZFAR: f32 = 1000.0;
 
-    const MATRIX_CORRECTION_FOR_WGPU: Matrix4<f32> = Matrix4::new(
+    pub(crate) const MATRIX_CORRECTION_FOR_WGPU: Matrix4<f32> = Matrix4::new(
         1.0, 0.0, 0.0, 0.0,
         0.0, 1.0, 0.0, 0.0,
         0.0, 0.0, 0.5, 0.0,
         0.0, 0.0, 0.5, 1.0,
     );
 
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = Matrix4::look_at_rh(
+    pub(crate) fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let view = Matrix4::look_to_rh(
             self.eye, 
-            self.target, 
-            cgmath::Vector3::unit_y()
+            self.forward, 
+            self.up
         );
 
         let projection = cgmath::perspective(
@@ -120,6 +280,29 @@ impl Camera {
 
         Self::MATRIX_CORRECTION_FOR_WGPU * projection * view
     }
+
+    // Returns the origin and (normalized) direction of the ray that passes through the given pixel.
+    // Pixel coordinates start at the top-left corner of the window, like winit's cursor position
+    pub fn screen_to_ray(&self, x: f32, y: f32, window_dims: (u32, u32)) -> (Point3<f32>, Vector3<f32>) {
+        let (width, height) = window_dims;
+
+        let ndc_x = 2.0 * x / width.max(1) as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height.max(1) as f32;
+
+        let inverse = self.build_view_projection_matrix()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        // wgpu's clip space depth ranges from 0 (near plane) to 1 (far plane)
+        let unproject = |depth: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            Point3::from_homogeneous(point)
+        };
+
+        let (near, far) = (unproject(0.0), unproject(1.0));
+
+        (near, (far - near).normalize())
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -179,6 +362,21 @@ impl CameraBuilder {
         self
     }
 
+    pub fn mode(mut self, mode: CameraMode) -> Self {
+        self.0.mode = mode;
+        self
+    }
+
+    pub fn roll(mut self, roll: f32) -> Self {
+        self.0.roll = roll;
+        self
+    }
+
+    pub fn follow(mut self, entity: world::EntityHandle, offset: Vector3<f32>) -> Self {
+        self.0.follow = Some((entity, offset));
+        self
+    }
+
     pub fn build(mut self) -> Camera {
         self.0.update();
         self.0
@@ -204,4 +402,124 @@ impl CameraUniform {
         self.position = [camera.eye.x, camera.eye.y, camera.eye.z, 1.0];
         self.projection = camera.build_view_projection_matrix().into();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use cgmath::{ InnerSpace, Point3, Vector3 };
+
+    use super::{ Camera, CameraBuilder, CameraMode };
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    // The view must be usable, with up perpendicular to the direction the eye faces
+    fn assert_valid_view(camera: &Camera) {
+        let matrix: [[f32; 4]; 4] = camera.build_view_projection_matrix().into();
+        assert!(matrix.iter().flatten().all(|value| value.is_finite()), "{:?}", matrix);
+        assert!(camera.up.dot(camera.direction()).abs() < 1e-4);
+        assert!((camera.up.magnitude() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbit_looks_at_the_target() {
+        let camera = CameraBuilder::new()
+            .target(Point3::new(1.0, 2.0, 3.0))
+            .distance(5.0)
+            .pitch(0.0)
+            .yaw(0.0)
+            .build();
+
+        assert_eq!(camera.eye(), Point3::new(1.0, 2.0, 8.0));
+        assert!(close(camera.direction(), -Vector3::unit_z()));
+        assert_valid_view(&camera);
+    }
+
+    #[test]
+    fn first_person_looks_from_the_target() {
+        let mut camera = CameraBuilder::new()
+            .mode(CameraMode::FirstPerson)
+            .target(Point3::new(1.0, 2.0, 3.0))
+            .pitch(0.0)
+            .yaw(FRAC_PI_2)
+            .build();
+
+        assert_eq!(camera.eye(), Point3::new(1.0, 2.0, 3.0));
+        assert!(close(camera.direction(), -Vector3::unit_x()));
+
+        // Movement stays level, even when looking down
+        camera.set_pitch(0.5);
+        camera.move_relative(1.0, 0.0, 0.0);
+        assert!(close(camera.eye() - Point3::new(1.0, 2.0, 3.0), -Vector3::unit_x()));
+    }
+
+    #[test]
+    fn free_fly_rolls_and_moves_along_the_view() {
+        let mut camera = CameraBuilder::new()
+            .mode(CameraMode::FreeFly)
+            .pitch(0.0)
+            .yaw(0.0)
+            .roll(FRAC_PI_2)
+            .build();
+
+        // Facing -Z, banked a quarter turn to the right
+        assert!(close(camera.up, Vector3::unit_x()));
+        assert_valid_view(&camera);
+
+        camera.move_relative(0.0, 0.0, 2.0);
+        assert!(close(camera.eye() - Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn looking_straight_up_or_down_has_a_valid_view() {
+        for pitch in [FRAC_PI_2, -FRAC_PI_2] {
+            for mode in [CameraMode::FirstPerson, CameraMode::FreeFly] {
+                let camera = CameraBuilder::new().mode(mode).pitch(pitch).yaw(0.3).build();
+                assert_valid_view(&camera);
+            }
+        }
+
+        for eye in [Point3::new(0.0, 5.0, 0.0), Point3::new(0.0, -5.0, 0.0)] {
+            let camera = CameraBuilder::new().mode(CameraMode::LookAt { eye } ).build();
+            assert_valid_view(&camera);
+        }
+    }
+
+    #[test]
+    fn look_at_keeps_its_direction_when_the_eye_reaches_the_target() {
+        let mut camera = CameraBuilder::new()
+            .mode(CameraMode::LookAt { eye: Point3::new(0.0, 0.0, 5.0) } )
+            .build();
+        assert!(close(camera.direction(), -Vector3::unit_z()));
+
+        camera.set_target(Point3::new(0.0, 0.0, 5.0));
+        assert!(close(camera.direction(), -Vector3::unit_z()));
+        assert_valid_view(&camera);
+    }
+
+    #[test]
+    fn screen_to_ray() {
+        let camera = CameraBuilder::new()
+            .mode(CameraMode::FirstPerson)
+            .target(Point3::new(1.0, 2.0, 3.0))
+            .pitch(0.3)
+            .yaw(-0.7)
+            .aspect(2.0)
+            .build();
+        let window = (200, 100);
+
+        let (origin, direction) = camera.screen_to_ray(100.0, 50.0, window);
+        assert!(close(direction, camera.direction()));
+        // Rays start on the near plane
+        assert!(((origin - camera.eye()).magnitude() - Camera::ZNEAR).abs() < 1e-4);
+
+        // The top-left corner is up and to the left of the center
+        let (.., corner) = camera.screen_to_ray(0.0, 0.0, window);
+        let right = camera.direction().cross(camera.up);
+        assert!(corner.dot(right) < 0.0);
+        assert!(corner.dot(camera.up) > 0.0);
+        assert!((corner.magnitude() - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{ error, fmt };
+
+use winit::error::OsError;
+
+// Reasons the engine can fail to start, see run
+#[derive(Debug)]
+pub enum EngineError {
+    // None of the adapters support the window's surface with the configured backends
+    NoAdapter,
+    DeviceRequest(wgpu::RequestDeviceError),
+    // The surface doesn't support any texture format on the chosen adapter
+    UnsupportedSurfaceFormat,
+    WindowCreation(OsError)
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoAdapter => write!(f, "no suitable graphics adapter was found"),
+            Self::DeviceRequest(error) => write!(f, "failed to request a device: {}", error),
+            Self::UnsupportedSurfaceFormat => write!(f, "the surface has no supported texture formats"),
+            Self::WindowCreation(error) => write!(f, "failed to create the window: {}", error)
+        }
+    }
+}
+
+impl error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::DeviceRequest(error) => Some(error),
+            Self::WindowCreation(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for EngineError {
+    fn from(error: wgpu::RequestDeviceError) -> Self {
+        Self::DeviceRequest(error)
+    }
+}
+
+impl From<OsError> for EngineError {
+    fn from(error: OsError) -> Self {
+        Self::WindowCreation(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{ Config, HeadlessState };
+
+    use super::EngineError;
+
+    #[test]
+    fn missing_adapter_is_reported() {
+        let config = Config { backends: wgpu::Backends::empty(), ..Config::default() };
+
+        let error = pollster::block_on(HeadlessState::with_config(8, 8, &config)).err().unwrap();
+        assert!(matches!(error, EngineError::NoAdapter));
+        assert_eq!(error.to_string(), "no suitable graphics adapter was found");
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn wrapped_errors_are_sources() {
+        let error = EngineError::from(wgpu::RequestDeviceError);
+
+        assert!(matches!(error, EngineError::DeviceRequest(..)));
+        assert!(error.to_string().starts_with("failed to request a device: "));
+        assert!(error.source().unwrap().is::<wgpu::RequestDeviceError>());
+    }
+}
@@ -0,0 +1,105 @@
+use winit::dpi;
+
+use crate::{
+    state,
+    Config,
+    EngineError,
+    GameData
+};
+
+// Renders the World without a window, primarily for golden-image tests
+pub struct HeadlessState {
+    state: state::State
+}
+
+impl HeadlessState {
+    pub async fn new(width: u32, height: u32) -> Result<Self, EngineError> {
+        Self::with_config(width, height, &Config::default()).await
+    }
+
+    // Only the renderer settings of the Config are used (MSAA, clear color, backends and power preference)
+    pub async fn with_config(width: u32, height: u32, config: &Config) -> Result<Self, EngineError> {
+        let state = state::State::new_headless(
+            dpi::PhysicalSize::new(width, height),
+            config
+        ).await?;
+
+        Ok(Self { state })
+    }
+
+    pub fn data(&mut self) -> GameData<'_, 'static> {
+        self.state.game_data()
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.state.size.width, self.state.size.height)
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.state.resize(dpi::PhysicalSize::new(width, height));
+    }
+
+    // Advances the simulation by a single tick (entity lifetimes and physics)
+    pub fn update(&mut self) {
+        self.state.step(|_| ());
+    }
+
+    // Renders the current World and Camera, returning tightly packed RGBA8 (sRGB) pixels
+    // Rows are ordered from top to bottom
+    pub fn render_to_image(&mut self) -> Vec<u8> {
+        self.state.prepare(1.0);
+
+        // Offscreen targets never produce SurfaceErrors
+        self.state.render().expect("offscreen rendering failed");
+        self.state.read_pixels().expect("headless State must render to a texture")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use crate::{ camera, world::testing::Block };
+
+    use super::HeadlessState;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    fn pixel(image: &[u8], x: u32, y: u32) -> &[u8] {
+        let offset = ((y * WIDTH + x) * 4) as usize;
+        &image[offset..(offset + 4)]
+    }
+
+    #[test]
+    fn empty_world_renders_clear_color() {
+        let mut state = pollster::block_on(HeadlessState::new(WIDTH, HEIGHT)).unwrap();
+
+        let image = state.render_to_image();
+        assert_eq!(image.len(), (WIDTH * HEIGHT * 4) as usize);
+        assert!(image.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn lit_tile_is_drawn_in_front_of_camera() {
+        let mut state = pollster::block_on(HeadlessState::new(WIDTH, HEIGHT)).unwrap();
+
+        let data = state.data();
+        data.world.add_tile(Block::at(0, 0, 0));
+        data.world.add_light(Point3::new(0.0, 0.0, 3.0), [1.0, 1.0, 1.0, 1.0]);
+        *data.camera = camera::CameraBuilder::new()
+            .target(Point3::new(0.0, 0.0, 0.0))
+            .distance(4.0)
+            .pitch(0.0)
+            .yaw(0.0)
+            .aspect(WIDTH as f32 / HEIGHT as f32)
+            .build();
+
+        let image = state.render_to_image();
+
+        // The cube's front face covers the middle of the image, the corners are empty
+        let center = pixel(&image, WIDTH / 2, HEIGHT / 2);
+        assert!(center[0] > 0 && center[0] == center[1] && center[1] == center[2]);
+        assert_eq!(pixel(&image, 0, 0), [0, 0, 0, 255]);
+    }
+}
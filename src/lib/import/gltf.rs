@@ -0,0 +1,177 @@
+use std::{ collections::HashSet, fs, io, path };
+
+use base64::Engine;
+
+use cgmath::{
+    InnerSpace,
+    Matrix,
+    Matrix4,
+    SquareMatrix,
+    Vector4
+};
+
+use crate::world::Triangles;
+
+// Loads a glTF 2.0 file, either binary (.glb) or JSON (.gltf).
+// Buffers may be embedded (as data URIs) or stored in files next to the asset
+pub fn load_gltf<P: AsRef<path::Path>>(path: P) -> io::Result<Triangles> {
+    let path = path.as_ref();
+    let directory = path.parent().unwrap_or_else(|| path::Path::new(""));
+
+    build_object_data(&fs::read(path)?, |uri| fs::read(directory.join(uri)))
+}
+
+// Same as load_gltf, but only supports assets whose buffers are embedded
+pub fn parse_gltf(bytes: &[u8]) -> io::Result<Triangles> {
+    build_object_data(bytes, |uri| Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("external buffer {} can't be loaded", uri)
+    )))
+}
+
+// Flattens the default scene (or the first scene) into a single mesh.
+// Vertex colors are the product of COLOR_0 and the material's base color factor
+fn build_object_data<F>(bytes: &[u8], load_external: F) -> io::Result<Triangles>
+    where F: Fn(&str) -> io::Result<Vec<u8>> {
+
+    let ::gltf::Gltf { document, mut blob } = ::gltf::Gltf::from_slice(bytes).map_err(invalid_data)?;
+
+    let buffers = document
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            ::gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| invalid_data("missing binary chunk")),
+            ::gltf::buffer::Source::Uri(uri) => match uri.strip_prefix("data:") {
+                Some(data) => {
+                    let (.., encoded) = data
+                        .split_once(";base64,")
+                        .ok_or_else(|| invalid_data("unsupported data URI"))?;
+
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .map_err(invalid_data)
+                },
+                None => load_external(uri)
+            }
+        } )
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| invalid_data("no scenes"))?;
+
+    let mut triangles = Triangles::default();
+    let mut visited = HashSet::new();
+    for node in scene.nodes() {
+        push_node(&mut triangles, &buffers, &node, Matrix4::identity(), &mut visited)?;
+    }
+
+    Ok(triangles)
+}
+
+// Each node can only be reached once, nodes with several parents (or cycles) are rejected
+fn push_node(
+    triangles: &mut Triangles,
+    buffers: &[Vec<u8>],
+    node: &::gltf::Node,
+    parent: Matrix4<f32>,
+    visited: &mut HashSet<usize>
+) -> io::Result<()> {
+    if !visited.insert(node.index()) {
+        return Err(invalid_data(format!("node {} is reachable more than once", node.index())));
+    }
+
+    let transform = parent * Matrix4::from(node.transform().matrix());
+
+    // Normals are transformed by the inverse transpose, which preserves them under non-uniform scaling
+    let normal_transform = transform
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(transform);
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            // Points and lines have no surface to shade
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| data.as_slice()));
+
+            let positions = match reader.read_positions() {
+                Some(positions) => positions
+                    .map(|p| (transform * Vector4::new(p[0], p[1], p[2], 1.0)).truncate().into())
+                    .collect::<Vec<[f32; 3]>>(),
+                None => continue
+            };
+
+            let normals = reader.read_normals().map(|normals| normals
+                .map(|n| (normal_transform * Vector4::new(n[0], n[1], n[2], 0.0))
+                    .truncate()
+                    .normalize()
+                    .into())
+                .collect::<Vec<[f32; 3]>>());
+
+            let base_color = primitive.material().pbr_metallic_roughness().base_color_factor();
+            let colors = match reader.read_colors(0) {
+                Some(colors) => colors
+                    .into_rgb_f32()
+                    .map(|c| [c[0] * base_color[0], c[1] * base_color[1], c[2] * base_color[2]])
+                    .collect::<Vec<_>>(),
+                None => vec![[base_color[0], base_color[1], base_color[2]]; positions.len()]
+            };
+
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..positions.len() as u32).collect()
+            };
+
+            // Mirroring transforms flip the winding of every triangle
+            let indices = if transform.determinant() < 0.0 {
+                indices.chunks_exact(3).flat_map(|t| [t[0], t[2], t[1]]).collect()
+            } else {
+                indices
+            };
+
+            let normals = normals.filter(|normals| normals.len() == positions.len());
+            if colors.len() != positions.len() {
+                return Err(invalid_data("mismatched vertex attributes"));
+            }
+
+            super::push_geometry(triangles, &positions, normals.as_deref(), &colors, &indices)?;
+        }
+    }
+
+    for child in node.children() {
+        push_node(triangles, buffers, &child, transform, visited)?;
+    }
+
+    Ok(())
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+    where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::parse_gltf;
+
+    #[test]
+    fn node_cycle_is_rejected() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "children": [1] }, { "children": [0] }]
+        }"#;
+
+        let error = parse_gltf(json.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    collections::HashMap,
+    io,
+    path,
+    rc::Rc
+};
+
+use cgmath::{ EuclideanSpace, Point3 };
+
+use crate::world::Triangles;
+
+// Geometry that can be shared by any number of entities without being copied
+#[derive(Clone)]
+pub struct Mesh(Rc<Triangles>);
+
+impl Mesh {
+    pub fn new(triangles: Triangles) -> Self {
+        Self(Rc::new(triangles))
+    }
+
+    pub fn triangles(&self) -> &Triangles {
+        &self.0
+    }
+
+    // Identifies the shared geometry, clones of a Mesh have the same key.
+    // Keys are only unique while a clone of the Mesh is alive
+    pub(crate) fn key(&self) -> *const Triangles {
+        Rc::as_ptr(&self.0)
+    }
+
+    // Returns a copy of the mesh centered on the given point, for use in Drawable::build_object_data
+    pub fn build_object_data(&self, center: Point3<f32>) -> Triangles {
+        let mut triangles = self.0.as_ref().clone();
+        triangles.translate(center.to_vec());
+        triangles
+    }
+}
+
+// Loads each file only once, subsequent loads share the existing Mesh
+#[derive(Default)]
+pub struct MeshCache {
+    meshes: HashMap<path::PathBuf, Mesh>
+}
+
+impl MeshCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The format is determined by the file's extension (.obj, .gltf, .glb or .vox)
+    pub fn load<P: AsRef<path::Path>>(&mut self, path: P) -> io::Result<Mesh> {
+        let path = path.as_ref();
+        if let Some(mesh) = self.meshes.get(path) {
+            return Ok(mesh.clone());
+        }
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let triangles = match extension.as_deref() {
+            Some("obj") => super::load_obj(path)?,
+            Some("gltf" | "glb") => super::load_gltf(path)?,
+            Some("vox") => super::Vox::open(path)?.build_object_data(),
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported mesh format: {}", path.display())
+            ))
+        };
+
+        let mesh = Mesh::new(triangles);
+        self.meshes.insert(path.to_path_buf(), mesh.clone());
+
+        Ok(mesh)
+    }
+}
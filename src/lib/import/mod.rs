@@ -0,0 +1,63 @@
+// Loaders that build tiles and geometry from files authored in other tools
+pub(crate) mod vox;
+pub use vox::{ Vox, VoxModel };
+
+pub(crate) mod obj;
+pub use obj::load_obj;
+
+pub(crate) mod gltf;
+pub use self::gltf::{ load_gltf, parse_gltf };
+
+pub(crate) mod mesh;
+pub use mesh::{ Mesh, MeshCache };
+
+use std::io;
+
+use cgmath::{ InnerSpace, Vector3 };
+
+use crate::{
+    vertex::Vertex,
+    world::Triangles
+};
+
+// Appends indexed geometry to the given Triangles.
+// When the source has no normals, each triangle gets its own (flat shaded) vertices
+pub(crate) fn push_geometry(
+    triangles: &mut Triangles,
+    positions: &[[f32; 3]],
+    normals: Option<&[[f32; 3]]>,
+    colors: &[[f32; 3]],
+    indices: &[u32]
+) -> io::Result<()> {
+    if !indices.len().is_multiple_of(3) || indices.iter().any(|&i| i as usize >= positions.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed indices"));
+    }
+
+    let base = triangles.vertices.len() as u32;
+
+    match normals {
+        Some(normals) => {
+            triangles.vertices.extend(positions.iter().zip(normals).zip(colors).map(|((&position, &normal), &color)| {
+                Vertex { position, color, normal }
+            } ));
+
+            triangles.indices.extend(indices.iter().map(|i| base + i));
+        },
+        None => for (face, triangle) in indices.chunks_exact(3).enumerate() {
+            let [a, b, c] = [0, 1, 2].map(|k| Vector3::from(positions[triangle[k] as usize]));
+            let normal = (b - a).cross(c - a);
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { normal };
+
+            triangles.vertices.extend(triangle.iter().map(|&i| Vertex {
+                position: positions[i as usize],
+                color: colors[i as usize],
+                normal: normal.into()
+            } ));
+
+            let first = base + face as u32 * 3;
+            triangles.indices.extend([first, first + 1, first + 2]);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::{ io, path };
+
+use crate::world::Triangles;
+
+// Loads a Wavefront OBJ file, along with the materials referenced by its mtllib statements.
+// Vertex colors are taken from the file if present (v x y z r g b),
+// otherwise from the diffuse color (Kd) of each face's material
+pub fn load_obj<P: AsRef<path::Path>>(path: P) -> io::Result<Triangles> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true
+    };
+
+    let (models, materials) = tobj::load_obj(path.as_ref(), &options).map_err(invalid_data)?;
+
+    // A missing or malformed material library shouldn't prevent the geometry from loading
+    let materials = materials.unwrap_or_default();
+
+    let mut triangles = Triangles::default();
+    for model in models {
+        let mesh = model.mesh;
+
+        let positions = mesh.positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect::<Vec<_>>();
+
+        let normals = (mesh.normals.len() == mesh.positions.len()).then(|| mesh.normals
+            .chunks_exact(3)
+            .map(|n| [n[0], n[1], n[2]])
+            .collect::<Vec<_>>());
+
+        let colors = if mesh.vertex_color.len() == mesh.positions.len() {
+            mesh.vertex_color
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect::<Vec<_>>()
+        } else {
+            let diffuse = mesh.material_id
+                .and_then(|id| materials.get(id))
+                .and_then(|material| material.diffuse)
+                .unwrap_or([1.0; 3]);
+
+            vec![diffuse; positions.len()]
+        };
+
+        super::push_geometry(
+            &mut triangles,
+            &positions,
+            normals.as_deref(),
+            &colors,
+            &mesh.indices
+        )?;
+    }
+
+    Ok(triangles)
+}
+
+fn invalid_data(error: tobj::LoadError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
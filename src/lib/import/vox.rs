@@ -0,0 +1,583 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path
+};
+
+use cgmath::{ Point3, Vector3 };
+
+use crate::world::{
+    self,
+    chunk,
+    mesher,
+    Tile,
+    Triangles
+};
+
+// A single model from a .vox file
+pub struct VoxModel {
+    // The model's dimensions in MagicaVoxel's coordinates (z points up)
+    pub size: [i32; 3],
+    // The color of each voxel, in the engine's coordinates (y points up).
+    // Positions are relative to the model's pivot (its center, as in MagicaVoxel)
+    pub voxels: HashMap<Point3<i16>, [f32; 3]>
+}
+
+impl VoxModel {
+    // Builds the model's mesh around the origin (see Triangles::translate)
+    pub fn build_object_data(&self) -> Triangles {
+        build_object_data(&self.voxels)
+    }
+}
+
+// The contents of a MagicaVoxel (.vox) file.
+// Models are placed according to the file's scene graph (transforms, groups and shapes),
+// files without a scene graph place each of their models at the origin
+pub struct Vox {
+    pub models: Vec<VoxModel>,
+    // Every voxel in the scene, in the engine's coordinates (y points up)
+    pub voxels: HashMap<Point3<i16>, [f32; 3]>
+}
+
+impl Vox {
+    pub fn open<P: AsRef<path::Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != b"VOX " {
+            return Err(invalid_data("not a .vox file"));
+        }
+
+        // Later versions only add chunk types, which are skipped if unknown
+        reader.i32()?;
+
+        let (id, _, mut main) = reader.chunk()?;
+        if id != b"MAIN" {
+            return Err(invalid_data("missing MAIN chunk"));
+        }
+
+        let mut sizes = Vec::new();
+        let mut models = Vec::new();
+        let mut palette = default_palette();
+        let mut nodes = HashMap::new();
+
+        while !main.0.is_empty() {
+            let (id, mut content, ..) = main.chunk()?;
+
+            match id {
+                b"SIZE" => sizes.push([content.i32()?, content.i32()?, content.i32()?]),
+                b"XYZI" => {
+                    let count = content.len()?;
+                    let voxels = content.take(count * 4)?
+                        .chunks_exact(4)
+                        .map(|voxel| [voxel[0], voxel[1], voxel[2], voxel[3]])
+                        .collect::<Vec<_>>();
+
+                    models.push(voxels);
+                },
+                // Color indices start at 1, so the palette is shifted by 1
+                b"RGBA" => {
+                    let colors = content.take(256 * 4)?.chunks_exact(4).take(255);
+                    for (index, color) in colors.enumerate() {
+                        palette[index + 1] = [color[0], color[1], color[2]];
+                    }
+                },
+                b"nTRN" => {
+                    let id = content.i32()?;
+                    content.dict()?;
+
+                    let child = content.i32()?;
+
+                    // Reserved and layer ids
+                    content.i32()?;
+                    content.i32()?;
+
+                    // Animated transforms aren't supported, only the first frame is used
+                    let frame = match content.len()? {
+                        0 => HashMap::new(),
+                        _ => content.dict()?
+                    };
+
+                    nodes.insert(id, Node::Transform(Transform::parse(&frame)?, child));
+                },
+                b"nGRP" => {
+                    let id = content.i32()?;
+                    content.dict()?;
+
+                    let children = (0..content.len()?)
+                        .map(|_| content.i32())
+                        .collect::<io::Result<Vec<_>>>()?;
+
+                    nodes.insert(id, Node::Group(children));
+                },
+                b"nSHP" => {
+                    let id = content.i32()?;
+                    content.dict()?;
+
+                    let mut shape_models = Vec::new();
+                    for _ in 0..content.len()? {
+                        shape_models.push(content.i32()?);
+                        content.dict()?;
+                    }
+
+                    nodes.insert(id, Node::Shape(shape_models));
+                },
+                // Materials, layers, cameras, etc. don't affect the geometry
+                _ => {  }
+            }
+        }
+
+        if sizes.len() != models.len() {
+            return Err(invalid_data("mismatched SIZE and XYZI chunks"));
+        }
+
+        let scene = Scene { nodes, sizes, models, palette };
+
+        let models = (0..scene.models.len())
+            .map(|model| {
+                let mut voxels = HashMap::new();
+                scene.place_model(model, &Transform::IDENTITY, &mut voxels)?;
+
+                Ok(VoxModel { size: scene.sizes[model], voxels })
+            } )
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut voxels = HashMap::new();
+        if scene.nodes.is_empty() {
+            for model in models.iter() {
+                voxels.extend(model.voxels.iter());
+            }
+        } else {
+            // The root of the scene graph is always the first transform node
+            scene.place_node(0, &Transform::IDENTITY, 0, &mut voxels)?;
+        }
+
+        Ok(Self { models, voxels })
+    }
+
+    // Builds the mesh of the entire scene (see Triangles::translate)
+    pub fn build_object_data(&self) -> Triangles {
+        build_object_data(&self.voxels)
+    }
+
+    // Adds a tile for each voxel in the scene, which is constructed from its position and color.
+    // Voxels that the offset moves beyond the edge of the World are skipped
+    pub fn add_to_world<T, F>(&self, world: &mut world::World, offset: Vector3<i16>, tile: F)
+        where T: Tile + 'static, F: Fn(Point3<i16>, [f32; 3]) -> T {
+
+        for (position, color) in self.voxels.iter() {
+            let moved = [
+                position.x.checked_add(offset.x),
+                position.y.checked_add(offset.y),
+                position.z.checked_add(offset.z)
+            ];
+
+            if let [Some(x), Some(y), Some(z)] = moved {
+                world.add_tile(tile(Point3::new(x, y, z), *color));
+            }
+        }
+    }
+}
+
+// Meshes the voxels one chunk at a time, so the dense grid used by the mesher stays small
+fn build_object_data(voxels: &HashMap<Point3<i16>, [f32; 3]>) -> Triangles {
+    let mut regions = voxels
+        .keys()
+        .map(|&position| chunk::Chunk::coordinates(position))
+        .collect::<Vec<_>>();
+
+    regions.sort_by_key(|region| (region.x, region.y, region.z));
+    regions.dedup();
+
+    let mut triangles = Triangles::default();
+    for region in regions {
+        mesher::greedy_mesh(
+            region * chunk::CHUNK_SIZE,
+            [chunk::CHUNK_SIZE; 3],
+            |position| voxels.get(&position).copied(),
+            &mut triangles
+        );
+    }
+
+    triangles
+}
+
+// MagicaVoxel's z axis points up, the engine's y axis does.
+// Fails if the position doesn't fit in the engine's coordinates
+fn to_engine(position: [i32; 3]) -> io::Result<Point3<i16>> {
+    let coordinate = |value: i32| i16::try_from(value)
+        .map_err(|_| invalid_data("voxel lies outside of the engine's coordinate range"));
+
+    Ok(Point3::new(
+        coordinate(position[0])?,
+        coordinate(position[2])?,
+        coordinate(position[1].saturating_neg())?
+    ))
+}
+
+enum Node {
+    // A transform along with the id of the node it applies to
+    Transform(Transform, i32),
+    Group(Vec<i32>),
+    Shape(Vec<i32>)
+}
+
+struct Scene {
+    nodes: HashMap<i32, Node>,
+    sizes: Vec<[i32; 3]>,
+    // The x, y, z and color index of each voxel
+    models: Vec<Vec<[u8; 4]>>,
+    palette: [[u8; 3]; 256]
+}
+
+impl Scene {
+    fn place_node(
+        &self,
+        id: i32,
+        transform: &Transform,
+        depth: usize,
+        voxels: &mut HashMap<Point3<i16>, [f32; 3]>
+    ) -> io::Result<()> {
+        // Every node can appear at most once along a path from the root
+        if depth > self.nodes.len() {
+            return Err(invalid_data("scene graph contains a cycle"));
+        }
+
+        match self.nodes.get(&id) {
+            Some(Node::Transform(local, child)) => {
+                self.place_node(*child, &transform.then(local)?, depth + 1, voxels)?;
+            },
+            Some(Node::Group(children)) => for child in children {
+                self.place_node(*child, transform, depth + 1, voxels)?;
+            },
+            Some(Node::Shape(models)) => for &model in models {
+                if model < 0 || model as usize >= self.models.len() {
+                    return Err(invalid_data("shape refers to a missing model"));
+                }
+
+                self.place_model(model as usize, transform, voxels)?;
+            },
+            None => return Err(invalid_data("scene graph refers to a missing node"))
+        }
+
+        Ok(())
+    }
+
+    // Models are positioned relative to their center
+    fn place_model(
+        &self,
+        model: usize,
+        transform: &Transform,
+        voxels: &mut HashMap<Point3<i16>, [f32; 3]>
+    ) -> io::Result<()> {
+        let size = self.sizes[model];
+
+        for &[x, y, z, index] in self.models[model].iter() {
+            let position = transform.apply([
+                x as i32 - size[0] / 2,
+                y as i32 - size[1] / 2,
+                z as i32 - size[2] / 2
+            ])?;
+
+            let color = self.palette[index as usize].map(|c| c as f32 / 255.0);
+
+            voxels.insert(to_engine(position)?, color);
+        }
+
+        Ok(())
+    }
+}
+
+// A rotation (restricted to multiples of 90 degrees) followed by a translation
+struct Transform {
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3]
+}
+
+impl Transform {
+    const IDENTITY: Self = Self {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0; 3]
+    };
+
+    fn parse(frame: &HashMap<String, String>) -> io::Result<Self> {
+        let mut transform = Self::IDENTITY;
+
+        // The rotation is packed into a single byte. Bits 0-1 and 2-3 hold the column
+        // of the non-zero entry in the first and second rows, bits 4-6 hold the sign of each row
+        if let Some(rotation) = frame.get("_r") {
+            let rotation = rotation.parse::<u8>().map_err(|_| invalid_data("malformed rotation"))?;
+
+            let first = (rotation & 3) as usize;
+            let second = ((rotation >> 2) & 3) as usize;
+            if first > 2 || second > 2 || first == second {
+                return Err(invalid_data("malformed rotation"));
+            }
+
+            let columns = [first, second, 3 - first - second];
+            for (row, column) in columns.into_iter().enumerate() {
+                transform.rotation[row] = [0; 3];
+                transform.rotation[row][column] = if rotation & (16 << row) == 0 { 1 } else { -1 };
+            }
+        }
+
+        if let Some(translation) = frame.get("_t") {
+            let components = translation
+                .split_whitespace()
+                .map(|component| component.parse::<i32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_data("malformed translation"))?;
+
+            transform.translation = components
+                .try_into()
+                .map_err(|_| invalid_data("malformed translation"))?;
+        }
+
+        Ok(transform)
+    }
+
+    // Fails if the result doesn't fit in an i32, translations are read from the file
+    fn apply(&self, position: [i32; 3]) -> io::Result<[i32; 3]> {
+        let mut result = self.translation;
+        for (row, component) in result.iter_mut().enumerate() {
+            for (column, value) in position.iter().enumerate() {
+                *component = self.rotation[row][column]
+                    .checked_mul(*value)
+                    .and_then(|rotated| component.checked_add(rotated))
+                    .ok_or_else(|| invalid_data("transform is out of range"))?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Applies the given (child) transform first, followed by this one
+    fn then(&self, child: &Self) -> io::Result<Self> {
+        // The entries of both rotations are -1, 0 or 1, so their product can't overflow
+        let mut rotation = [[0; 3]; 3];
+        for (row, entries) in rotation.iter_mut().enumerate() {
+            for (column, entry) in entries.iter_mut().enumerate() {
+                *entry = (0..3).map(|k| self.rotation[row][k] * child.rotation[k][column]).sum();
+            }
+        }
+
+        Ok(Self { rotation, translation: self.apply(child.translation)? })
+    }
+}
+
+// Reads the little-endian primitives that .vox files are composed of
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if count > self.0.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let (taken, remaining) = self.0.split_at(count);
+        self.0 = remaining;
+
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // Counts and sizes are stored as signed integers
+    fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative length"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.len()?;
+
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| invalid_data("malformed string"))
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        (0..self.len()?)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+
+    // Returns the chunk's id, its content and its children
+    fn chunk(&mut self) -> io::Result<(&'a [u8], Reader<'a>, Reader<'a>)> {
+        let id = self.take(4)?;
+        let content = self.len()?;
+        let children = self.len()?;
+
+        Ok((id, Reader(self.take(content)?), Reader(self.take(children)?)))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// The palette used by files without an RGBA chunk.
+// Index 0 is empty, followed by a 6x6x6 color cube (without black)
+// and ramps of red, green, blue and gray
+fn default_palette() -> [[u8; 3]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0; 3]; 256];
+    for (index, color) in palette[1..216].iter_mut().enumerate() {
+        *color = [CUBE[index / 36], CUBE[(index / 6) % 6], CUBE[index % 6]];
+    }
+
+    for (index, &level) in RAMP.iter().enumerate() {
+        palette[216 + index] = [level, 0, 0];
+        palette[226 + index] = [0, level, 0];
+        palette[236 + index] = [0, 0, level];
+        palette[246 + index] = [level; 3];
+    }
+
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ collections::HashMap, io };
+
+    use cgmath::{ Point3, Vector3 };
+
+    use crate::world::{ testing::Block, World };
+
+    use super::Vox;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    fn size(size: [i32; 3]) -> Vec<u8> {
+        let content = size.iter().flat_map(|s| s.to_le_bytes()).collect::<Vec<_>>();
+        chunk(b"SIZE", &content, &[])
+    }
+
+    fn xyzi(voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut content = (voxels.len() as i32).to_le_bytes().to_vec();
+        content.extend(voxels.iter().flatten());
+        chunk(b"XYZI", &content, &[])
+    }
+
+    // A file with the given chunks inside of its MAIN chunk
+    fn vox(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150i32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[], &chunks.concat()));
+        bytes
+    }
+
+    #[test]
+    fn default_palette() {
+        let bytes = vox(&[size([2, 2, 2]), xyzi(&[[0, 0, 0, 1], [1, 0, 1, 216]])]);
+        let vox = Vox::parse(&bytes).unwrap();
+
+        assert_eq!(vox.models.len(), 1);
+        assert_eq!(vox.models[0].size, [2, 2, 2]);
+
+        // Positions are centered on the model, and z becomes y
+        assert_eq!(vox.voxels.len(), 2);
+        assert_eq!(vox.voxels[&Point3::new(-1, -1, 1)], [1.0; 3]);
+        assert_eq!(vox.voxels[&Point3::new(0, 0, 1)], [0xee as f32 / 255.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rgba_palette_is_shifted_by_one() {
+        let palette = (0..=255u8).flat_map(|i| [i, 0, 0, 255]).collect::<Vec<_>>();
+        let bytes = vox(&[
+            size([1, 1, 1]),
+            xyzi(&[[0, 0, 0, 3]]),
+            chunk(b"RGBA", &palette, &[])
+        ] );
+
+        let vox = Vox::parse(&bytes).unwrap();
+        assert_eq!(vox.voxels[&Point3::new(0, 0, 0)], [2.0 / 255.0, 0.0, 0.0]);
+    }
+
+    fn dict(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = (entries.len() as i32).to_le_bytes().to_vec();
+        for string in entries.iter().flat_map(|(key, value)| [key, value]) {
+            bytes.extend_from_slice(&(string.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(string.as_bytes());
+        }
+
+        bytes
+    }
+
+    // A transform node (id 0) with the given translation, applied to a shape node (id 1) showing model 0
+    fn translated(translation: &str) -> Vec<Vec<u8>> {
+        let mut transform = 0i32.to_le_bytes().to_vec();
+        transform.extend(dict(&[]));
+
+        // The child, reserved id, layer and number of frames
+        for value in [1i32, -1, 0, 1] {
+            transform.extend_from_slice(&value.to_le_bytes());
+        }
+        transform.extend(dict(&[("_t", translation)]));
+
+        let mut shape = 1i32.to_le_bytes().to_vec();
+        shape.extend(dict(&[]));
+        shape.extend_from_slice(&1i32.to_le_bytes());
+        shape.extend_from_slice(&0i32.to_le_bytes());
+        shape.extend(dict(&[]));
+
+        vec![chunk(b"nTRN", &transform, &[]), chunk(b"nSHP", &shape, &[])]
+    }
+
+    #[test]
+    fn translation_is_applied() {
+        let mut chunks = vec![size([1, 1, 1]), xyzi(&[[0, 0, 0, 1]])];
+        chunks.extend(translated("3 -4 5"));
+
+        let vox = Vox::parse(&vox(&chunks)).unwrap();
+        assert_eq!(vox.voxels.keys().collect::<Vec<_>>(), [&Point3::new(3, 5, 4)]);
+    }
+
+    #[test]
+    fn extreme_translation_is_rejected() {
+        let mut chunks = vec![size([4, 1, 1]), xyzi(&[[3, 0, 0, 1]])];
+        chunks.extend(translated("2147483647 0 0"));
+
+        let error = Vox::parse(&vox(&chunks)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn voxels_moved_beyond_the_world_are_skipped() {
+        let voxels = HashMap::from([
+            (Point3::new(32000, 0, 0), [1.0; 3]),
+            (Point3::new(0, 0, 0), [1.0; 3])
+        ] );
+        let vox = Vox { models: Vec::new(), voxels };
+
+        let mut world = World::default();
+        vox.add_to_world(&mut world, Vector3::new(1000, 0, 0), |position, color| {
+            Block { position: position.into(), color, ..Block::default() }
+        } );
+
+        assert!(world.contains_tile(&Point3::new(1000, 0, 0)));
+        assert_eq!(world.tiles().count(), 1);
+    }
+
+    #[test]
+    fn model_outside_of_i16_range_is_rejected() {
+        // Voxels are offset by half of the model's size, which doesn't fit in an i16
+        let bytes = vox(&[size([70000, 1, 1]), xyzi(&[[0, 0, 0, 1]])]);
+
+        let error = Vox::parse(&bytes).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,143 @@
+use cgmath::{
+    EuclideanSpace,
+    Matrix3,
+    Matrix4,
+    Point3,
+    Quaternion,
+    Vector3
+};
+
+// The per-instance transform of a piece of geometry
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Instance {
+    pub(crate) model: [[f32; 4]; 4],
+    // The inverse transpose of the model matrix's rotation and scale
+    pub(crate) normal: [[f32; 3]; 3]
+}
+
+impl Instance {
+    // Used by geometry that is already in world space (chunks and entities without a Mesh)
+    pub(crate) const IDENTITY: Self = Self {
+        model: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ],
+        normal: [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0]
+        ]
+    };
+
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = {
+        wgpu::vertex_attr_array![
+            3 => Float32x4,
+            4 => Float32x4,
+            5 => Float32x4,
+            6 => Float32x4,
+            7 => Float32x3,
+            8 => Float32x3,
+            9 => Float32x3
+        ]
+    };
+
+    // Scales the model before rotating it, then moves it to the given center
+    pub(crate) fn new(center: Point3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        let model = Matrix4::from_translation(center.to_vec())
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
+
+        // Collapsed axes have no meaningful normal, they are left unscaled
+        let inverse_scale = scale.map(|s| if s == 0.0 { 1.0 } else { s.recip() });
+        let rotation = Matrix3::from(rotation);
+        let normal = Matrix3::from_cols(
+            rotation.x * inverse_scale.x,
+            rotation.y * inverse_scale.y,
+            rotation.z * inverse_scale.z
+        );
+
+        Self { model: model.into(), normal: normal.into() }
+    }
+
+    pub(crate) fn description<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{
+        Deg,
+        InnerSpace,
+        Matrix3,
+        Matrix4,
+        Point3,
+        Quaternion,
+        Rotation3,
+        Transform,
+        Vector3
+    };
+
+    use super::Instance;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn identity() {
+        let instance = Instance::new(Point3::new(0.0, 0.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0));
+
+        assert_eq!(instance.model, Instance::IDENTITY.model);
+        assert_eq!(instance.normal, Instance::IDENTITY.normal);
+    }
+
+    #[test]
+    fn scales_then_rotates_then_translates() {
+        let instance = Instance::new(
+            Point3::new(1.0, 2.0, 3.0),
+            Quaternion::from_angle_z(Deg(90.0)),
+            Vector3::new(2.0, 1.0, 1.0)
+        );
+
+        let model = Matrix4::from(instance.model);
+        let point = model.transform_point(Point3::new(1.0, 0.0, 0.0));
+        assert!((point - Point3::new(1.0, 4.0, 3.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_to_scaled_surfaces() {
+        let instance = Instance::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Quaternion::from_angle_y(Deg(30.0)),
+            Vector3::new(3.0, 1.0, 0.5)
+        );
+
+        // A plane containing the y axis, at 45 degrees to the x and z axes
+        let tangent = Vector3::new(1.0, 0.0, -1.0);
+        let normal = Vector3::new(1.0, 0.0, 1.0);
+
+        let model = Matrix4::from(instance.model);
+        let tangent = model.transform_vector(tangent);
+        let normal = Matrix3::from(instance.normal) * normal;
+        assert!(tangent.dot(normal).abs() < 1e-5);
+    }
+
+    #[test]
+    fn collapsed_axes_keep_finite_normals() {
+        let instance = Instance::new(Point3::new(0.0, 0.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 2.0));
+
+        let normal = Matrix3::from(instance.normal);
+        assert!(close(normal * Vector3::unit_y(), Vector3::unit_y()));
+        assert!(close(normal * Vector3::unit_z(), Vector3::unit_z() * 0.5));
+    }
+}
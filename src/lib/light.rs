@@ -1,20 +1,242 @@
-pub(crate) const MAX_LIGHT_SOURCES: usize = 64;
+use cgmath::{
+    Matrix4,
+    Point3,
+    Vector4
+};
+
+// Contributions below this intensity are too dim to survive quantization,
+// they determine how far a light's influence reaches
+const LIGHT_CUTOFF: f32 = 1.0 / 4096.0;
+
+// The width and height (in pixels) of each screen tile lights are assigned to
+pub(crate) const LIGHT_TILE_SIZE: u32 = 16;
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub(crate) struct Light {
+    // The w component holds the light's radius of influence
     pub(crate) position: [f32; 4],
-    pub(crate) color: [f32; 4]
+    pub(crate) color: [f32; 4],
+    // The x component holds the row of the light's shadow maps in the atlas (-1 if it has none)
+    pub(crate) shadow: [i32; 4]
 }
 
 impl Default for Light {
     fn default() -> Self {
-        Self { position: [0.0; 4], color: [0.0; 4] }
+        Self { position: [0.0; 4], color: [0.0; 4], shadow: [-1, 0, 0, 0] }
+    }
+}
+
+impl Light {
+    pub(crate) fn new(position: Point3<f32>, color: [f32; 4]) -> Self {
+        Self {
+            position: [position.x, position.y, position.z, Self::radius(color)],
+            color,
+            ..Default::default()
+        }
+    }
+
+    // Mirrors the falloff in fs_main: (ambient + diffuse + specular) / distance^4 * intensity
+    fn radius(color: [f32; 4]) -> f32 {
+        let intensity = color[3].max(0.0);
+        let brightest = color[0].max(color[1]).max(color[2]).max(0.0);
+
+        (brightest * (intensity + 2.0) * intensity / LIGHT_CUTOFF).powf(0.25)
     }
 }
 
+#[derive(Default)]
+pub(crate) struct LightSources {
+    pub(crate) lights: Vec<Light>,
+    // Indices of the lights whose Drawable requested shadows
+    pub(crate) shadow_casters: Vec<usize>
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct LightSources {
-    pub(crate) light_uniforms: [Light; MAX_LIGHT_SOURCES]
-}
\ No newline at end of file
+pub(crate) struct LightGridUniform {
+    pub(crate) tile_size: u32,
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+    pub(crate) light_count: u32
+}
+
+// Assigns each light to the screen tiles its radius of influence overlaps,
+// so fragments only iterate over the lights that can affect them
+pub(crate) struct LightGrid {
+    pub(crate) uniform: LightGridUniform,
+    // The offset and length of each tile's slice of light_indices
+    pub(crate) tiles: Vec<[u32; 2]>,
+    pub(crate) light_indices: Vec<u32>
+}
+
+impl LightGrid {
+    pub(crate) fn build(
+        light_sources: &LightSources,
+        view_projection: Matrix4<f32>,
+        width: u32,
+        height: u32
+    ) -> Self {
+        let columns = width.div_ceil(LIGHT_TILE_SIZE).max(1);
+        let rows = height.div_ceil(LIGHT_TILE_SIZE).max(1);
+
+        let mut tile_lights = vec![Vec::new(); (columns * rows) as usize];
+
+        for (index, light) in light_sources.lights.iter().enumerate() {
+            let bounds = match screen_bounds(light, view_projection, width, height) {
+                Some(bounds) => bounds,
+                None => continue
+            };
+
+            let (min_column, min_row) = (
+                bounds[0] / LIGHT_TILE_SIZE,
+                bounds[1] / LIGHT_TILE_SIZE
+            );
+
+            let (max_column, max_row) = (
+                (bounds[2] / LIGHT_TILE_SIZE).min(columns - 1),
+                (bounds[3] / LIGHT_TILE_SIZE).min(rows - 1)
+            );
+
+            for row in min_row..=max_row {
+                for column in min_column..=max_column {
+                    tile_lights[(row * columns + column) as usize].push(index as u32);
+                }
+            }
+        }
+
+        let mut tiles = Vec::with_capacity(tile_lights.len());
+        let mut light_indices = Vec::new();
+        for mut lights in tile_lights {
+            tiles.push([light_indices.len() as u32, lights.len() as u32]);
+            light_indices.append(&mut lights);
+        }
+
+        Self {
+            uniform: LightGridUniform {
+                tile_size: LIGHT_TILE_SIZE,
+                columns,
+                rows,
+                light_count: light_sources.lights.len() as u32
+            },
+            tiles,
+            light_indices
+        }
+    }
+}
+
+// Conservatively projects a light's sphere of influence into pixel coordinates
+// Returns [min_x, min_y, max_x, max_y], or None if it is entirely off screen
+fn screen_bounds(
+    light: &Light,
+    view_projection: Matrix4<f32>,
+    width: u32,
+    height: u32
+) -> Option<[u32; 4]> {
+    let radius = light.position[3];
+    if radius <= 0.0 { return None; }
+
+    let full_screen = Some([0, 0, width, height]);
+
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for corner in 0..8 {
+        let offset = |axis: usize| if corner & (1 << axis) == 0 { -radius } else { radius };
+
+        let clip = view_projection * Vector4::new(
+            light.position[0] + offset(0),
+            light.position[1] + offset(1),
+            light.position[2] + offset(2),
+            1.0
+        );
+
+        // Part of the sphere is behind the camera, so it can't be projected
+        if clip.w <= f32::EPSILON { return full_screen; }
+
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+        min = [min[0].min(x), min[1].min(y)];
+        max = [max[0].max(x), max[1].max(y)];
+    }
+
+    if max[0] < -1.0 || min[0] > 1.0 || max[1] < -1.0 || min[1] > 1.0 {
+        return None;
+    }
+
+    // Convert from NDC to pixels, where y points down
+    let to_pixels = |ndc: f32, extent: u32| {
+        ((ndc.clamp(-1.0, 1.0) * 0.5 + 0.5) * extent as f32) as u32
+    };
+
+    Some([
+        to_pixels(min[0], width),
+        to_pixels(-max[1], height),
+        to_pixels(max[0], width),
+        to_pixels(-min[1], height)
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ ortho, perspective, Deg, Matrix4, Point3 };
+
+    use super::{ Light, LightGrid, LightSources };
+
+    // 4 by 4 tiles, showing x and y from -8 to 8 while looking down the z axis
+    const SIZE: u32 = 64;
+
+    fn orthographic() -> Matrix4<f32> {
+        ortho(-8.0, 8.0, -8.0, 8.0, 0.1, 100.0)
+    }
+
+    fn light(x: f32, y: f32, z: f32, radius: f32) -> Light {
+        Light { position: [x, y, z, radius], ..Light::default() }
+    }
+
+    // The lights in each tile, row by row
+    fn build(lights: Vec<Light>, view_projection: Matrix4<f32>) -> Vec<Vec<u32>> {
+        let grid = LightGrid::build(&LightSources { lights, ..LightSources::default() }, view_projection, SIZE, SIZE);
+        assert_eq!((grid.uniform.columns, grid.uniform.rows), (4, 4));
+
+        grid.tiles
+            .iter()
+            .map(|[offset, length]| grid.light_indices[*offset as usize..(offset + length) as usize].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn lights_are_assigned_to_the_tiles_they_overlap() {
+        // Within the top left tile, and straddling the bottom two rows of the right two columns
+        let tiles = build(vec![light(-7.0, 7.0, -10.0, 0.5), light(4.0, -4.0, -10.0, 1.0)], orthographic());
+
+        let expected = (0..16).map(|tile| match tile {
+            0 => vec![0],
+            10 | 11 | 14 | 15 => vec![1],
+            _ => vec![]
+        } );
+        assert!(tiles.into_iter().eq(expected));
+    }
+
+    #[test]
+    fn lights_off_screen_are_culled() {
+        let tiles = build(vec![light(12.0, 0.0, -10.0, 2.0), light(0.0, 0.0, -10.0, 0.0)], orthographic());
+
+        assert!(tiles.iter().all(|tile| tile.is_empty()));
+    }
+
+    #[test]
+    fn lights_around_the_camera_cover_the_screen() {
+        let view_projection = perspective(Deg(90.0), 1.0, 0.1, 100.0);
+        let tiles = build(vec![light(0.0, 0.0, 0.5, 1.0), light(0.0, 0.0, -5.0, 100.0)], view_projection);
+
+        assert!(tiles.iter().all(|tile| tile == &[0, 1]));
+    }
+
+    #[test]
+    fn radius_grows_with_brightness() {
+        let radius = |color| Light::new(Point3::new(0.0, 0.0, 0.0), color).position[3];
+
+        assert_eq!(radius([0.0, 0.0, 0.0, 1.0]), 0.0);
+        assert_eq!(radius([1.0, 1.0, 1.0, 0.0]), 0.0);
+        assert!(radius([1.0, 0.0, 0.0, 1.0]) < radius([1.0, 0.0, 0.0, 2.0]));
+        assert_eq!(radius([0.0, 0.5, 0.0, 1.0]), radius([0.5, 0.5, 0.5, 1.0]));
+    }
+}
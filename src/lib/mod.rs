@@ -1,11 +1,24 @@
 mod state;
 mod light;
+mod shadow;
 
 mod vertex;
 pub use vertex::Vertex;
 
+mod instance;
+
+mod headless;
+pub use headless::HeadlessState;
+
+mod error;
+pub use error::EngineError;
+
+mod scheduler;
+pub use scheduler::{ Scheduler, Coroutine, TaskId };
+
 pub mod camera;
 pub mod world;
+pub mod import;
 
 use std::time;
 
@@ -19,36 +32,143 @@ use winit::{
 
 #[derive(Clone)]
 pub struct Config {
-    pub fps: usize
+    // The number of times the game is updated each second,
+    // frames are rendered as often as possible in between.
+    // Sets the PhysicsConfig's timestep, unless game_init replaces the PhysicsConfig
+    pub fps: usize,
+    pub title: String,
+    // The inner size of the window in physical pixels, chosen by the platform if None
+    pub window_size: Option<(u32, u32)>,
+    // Borderless, on the current monitor
+    pub fullscreen: bool,
+    pub resizable: bool,
+    // Fifo waits for vertical sync, Immediate doesn't.
+    // Falls back to Fifo if the surface doesn't support the mode
+    pub present_mode: wgpu::PresentMode,
+    // The number of samples taken per pixel (MSAA), 1 disables it.
+    // Falls back to 1 if the surface's format can't be multisampled
+    pub msaa_samples: u32,
+    pub clear_color: wgpu::Color,
+    // The graphics APIs that may be used, and which adapter is preferred among them
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fps: 60,
+            title: String::from("engine_demo"),
+            window_size: None,
+            fullscreen: false,
+            resizable: true,
+            present_mode: wgpu::PresentMode::Fifo,
+            msaa_samples: 1,
+            clear_color: wgpu::Color::BLACK,
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default()
+        }
+    }
+}
+
+impl Config {
+    fn window_builder(&self) -> window::WindowBuilder {
+        let mut builder = window::WindowBuilder::new()
+            .with_title(&self.title)
+            .with_resizable(self.resizable);
+
+        if let Some((width, height)) = self.window_size {
+            builder = builder.with_inner_size(dpi::PhysicalSize::new(width, height));
+        }
+
+        if self.fullscreen {
+            builder = builder.with_fullscreen(Some(window::Fullscreen::Borderless(None)));
+        }
+
+        builder
+    }
+}
+
+// When the game falls behind by more than this many updates,
+// the remaining time is dropped instead of trying to catch up
+const MAX_UPDATES_PER_FRAME: usize = 5;
+
+// Consumes a whole timestep of the accumulated time for each update that should be run.
+// Avoids the spiral of death when updates take longer than the timestep,
+// by dropping the whole timesteps that are left after MAX_UPDATES_PER_FRAME
+fn take_updates(accumulated_time: &mut f32, timestep: f32) -> usize {
+    let mut updates = 0;
+    while *accumulated_time >= timestep && updates < MAX_UPDATES_PER_FRAME {
+        *accumulated_time -= timestep;
+        updates += 1;
+    }
+
+    if *accumulated_time >= timestep {
+        *accumulated_time %= timestep;
+    }
+
+    updates
 }
 
 pub struct GameData<'a, 'b> {
     pub world: &'a mut world::World<'b>,
     pub camera: &'a mut camera::Camera,
+    pub scheduler: &'a mut Scheduler,
+    // The number of seconds between updates (PhysicsConfig::timestep),
+    // regardless of the World's time scale (see World::delta_time)
+    pub dt: f32,
+    // The number of updates that have completed
+    pub tick: u64
 }
 
-pub async fn run<I: 'static, U: 'static, E: 'static>(
+impl<'a, 'b> GameData<'a, 'b> {
+    // A shorter-lived copy, for passing the GameData along without giving it up
+    pub fn reborrow(&mut self) -> GameData<'_, 'b> {
+        GameData {
+            world: self.world,
+            camera: self.camera,
+            scheduler: self.scheduler,
+            dt: self.dt,
+            tick: self.tick
+        }
+    }
+
+    // The contacts produced by the most recent physics tick, 
+    // events that aren't drained are discarded when the next tick begins
+    pub fn drain_contact_events(&mut self) -> impl Iterator<Item = world::ContactEvent> + '_ {
+        self.world.drain_contact_events()
+    }
+}
+
+// Only returns if the Window or the renderer couldn't be created,
+// otherwise the process exits when the Window is closed
+pub async fn run<I, U, E>(
     config: Config, 
     game_init: I,
     mut game_update: U, 
     mut process_events: E
-) where
-    I: FnOnce(GameData),
-    U: FnMut(GameData), 
-    E: FnMut(GameWindow, GameEvent, GameData) -> bool {
+) -> Result<(), EngineError> where
+    I: FnOnce(GameData) + 'static,
+    U: FnMut(GameData) + 'static, 
+    E: FnMut(GameWindow, GameEvent, GameData) -> bool + 'static {
 
     // Initialize the Window and EventLoop
     let event_loop = event_loop::EventLoop::new();
-    let window = window::WindowBuilder::new().build(&event_loop).unwrap();
+    let window = config.window_builder().build(&event_loop)?;
 
     // Contains ALL of the engine's mutable state...
-    let mut state = state::State::new(&window).await;
-
-    game_init(GameData { world: &mut state.world, camera: &mut state.camera } );
+    let mut state = state::State::new(&window, &config).await?;
 
     // ...except that related to frame time
-    let fps = (config.fps as f32).recip();
     let mut accumulated_time = 0.0;
+
+    state.world.set_physics_config(world::PhysicsConfig {
+        timestep: (config.fps as f32).recip(),
+        ..*state.world.physics_config()
+    } );
+
+    game_init(state.game_data());
+
     let mut current = time::Instant::now();
 
     // The game loop itself
@@ -62,21 +182,29 @@ pub async fn run<I: 'static, U: 'static, E: 'static>(
             event::Event::RedrawRequested(w_id) if w_id == window.id() => {
                 match state.render() {
                     Ok(..) => {  },
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                    // The surface has to be reconfigured before it can be drawn to again
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        state.resize(state.size)
+                    },
+                    // The frame is skipped, the next one is requested as usual
+                    Err(wgpu::SurfaceError::Timeout) => {  },
                     Err(wgpu::SurfaceError::OutOfMemory) => {
                         *control_flow = event_loop::ControlFlow::Exit 
-                    },
-                    Err(e) => eprintln!("{:?}", e)
+                    }
                 }
             },
 
-            // Redraw
-            event::Event::MainEventsCleared if accumulated_time >= fps => {
-                game_update(GameData { world: &mut state.world, camera: &mut state.camera } );
-                
-                state.update();
+            // Update at a fixed rate, then redraw
+            event::Event::MainEventsCleared => {
+                // Each tick simulates a single physics timestep
+                let timestep = state.world.physics_config().timestep;
+
+                for _ in 0..take_updates(&mut accumulated_time, timestep) {
+                    state.step(&mut game_update);
+                }
 
-                accumulated_time -= fps;
+                // The time left over places the frame between the last two ticks
+                state.prepare(accumulated_time / timestep);
 
                 window.request_redraw();
             },
@@ -115,7 +243,7 @@ pub async fn run<I: 'static, U: 'static, E: 'static>(
                             process_events(
                                 GameWindow::new(&window), 
                                 game_event, 
-                                GameData { world: &mut state.world, camera: &mut state.camera } 
+                                state.game_data()
                             );
                         }
                     }
@@ -129,7 +257,7 @@ pub async fn run<I: 'static, U: 'static, E: 'static>(
                     process_events(
                         GameWindow::new(&window), 
                         game_event, 
-                        GameData { world: &mut state.world, camera: &mut state.camera } 
+                        state.game_data()
                     );
                     
                 }   
@@ -203,4 +331,31 @@ impl GameEvent {
             WindowEvent::ThemeChanged(_) => None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{ take_updates, MAX_UPDATES_PER_FRAME };
+
+    #[test]
+    fn whole_timesteps_are_run() {
+        let mut accumulated_time = 0.6;
+        assert_eq!(take_updates(&mut accumulated_time, 0.25), 2);
+        assert_eq!(accumulated_time, 0.6 - 0.5);
+
+        // The remainder carries over into the next frame
+        accumulated_time += 0.2;
+        assert_eq!(take_updates(&mut accumulated_time, 0.25), 1);
+        assert!((accumulated_time - 0.05).abs() < 1e-6);
+
+        let mut accumulated_time = 0.2;
+        assert_eq!(take_updates(&mut accumulated_time, 0.25), 0);
+        assert_eq!(accumulated_time, 0.2);
+    }
+
+    #[test]
+    fn falling_behind_drops_time() {
+        let mut accumulated_time = 0.25 * 20.0 + 0.125;
+        assert_eq!(take_updates(&mut accumulated_time, 0.25), MAX_UPDATES_PER_FRAME);
+        assert_eq!(accumulated_time, 0.125);
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    time
+};
+
+use crate::GameData;
+
+type Callback = Box<dyn FnMut(GameData<'_, 'static>)>;
+type Condition = Box<dyn FnMut(&GameData<'_, 'static>) -> bool>;
+
+// Identifies a timer or coroutine, see Scheduler::cancel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(u64);
+
+// Runs timers and coroutines once per tick, after the game's update and before the physics step.
+// Time is measured by the World's clock, so tasks are frozen while it's paused
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: u64,
+    tasks: Vec<Task>,
+    // Tasks that were cancelled while the scheduler was running
+    cancelled: Vec<TaskId>,
+    // The World's elapsed time, and the number of ticks it wasn't paused for
+    now: f64,
+    ticks: u64
+}
+
+struct Task {
+    id: TaskId,
+    kind: TaskKind,
+    finished: bool
+}
+
+enum TaskKind {
+    Timer {
+        delay: f64,
+        // Measured from the World's time during the scheduler's next run, see Scheduler::anchor
+        due: Option<f64>,
+        // Repeating timers are rescheduled after they run
+        interval: Option<f64>,
+        callback: Callback
+    },
+    Coroutine(Coroutine)
+}
+
+impl Scheduler {
+    // Runs the callback once, after the given amount of simulated time.
+    // The delay starts with the tick that the timer is scheduled in, so a delay of one
+    // timestep scheduled during the game's update runs the callback during the next tick
+    pub fn after<F>(&mut self, delay: time::Duration, callback: F) -> TaskId
+        where F: FnOnce(GameData<'_, 'static>) + 'static {
+
+        let mut callback = Some(callback);
+        self.push(TaskKind::Timer {
+            delay: delay.as_secs_f64(),
+            due: None,
+            interval: None,
+            callback: Box::new(move |data| {
+                if let Some(callback) = callback.take() {
+                    callback(data);
+                }
+            } )
+        } )
+    }
+
+    // Runs the callback every interval until it's cancelled, starting one interval from now.
+    // It runs at most once per tick, so intervals shorter than a tick fall behind
+    pub fn every<F>(&mut self, interval: time::Duration, callback: F) -> TaskId
+        where F: FnMut(GameData<'_, 'static>) + 'static {
+
+        let interval = interval.as_secs_f64();
+        self.push(TaskKind::Timer {
+            delay: interval,
+            due: None,
+            interval: Some(interval),
+            callback: Box::new(callback)
+        } )
+    }
+
+    // The coroutine's first steps are run during the next tick
+    pub fn start(&mut self, coroutine: Coroutine) -> TaskId {
+        self.push(TaskKind::Coroutine(coroutine))
+    }
+
+    // Stops the timer or coroutine, cancelling a finished task does nothing
+    pub fn cancel(&mut self, id: TaskId) {
+        self.tasks.retain(|task| task.id != id);
+        self.cancelled.push(id);
+    }
+
+    fn push(&mut self, kind: TaskKind) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+
+        self.tasks.push(Task { id, kind, finished: false });
+
+        id
+    }
+
+    // The GameData's scheduler is the one being run
+    pub(crate) fn run(mut data: GameData<'_, 'static>) {
+        data.scheduler.now = data.world.elapsed();
+        if !data.world.is_paused() {
+            data.scheduler.ticks += 1;
+        }
+        data.scheduler.anchor();
+
+        // The tasks are moved out of the scheduler so that they can be given access to it
+        let mut tasks = mem::take(&mut data.scheduler.tasks);
+        for task in tasks.iter_mut() {
+            if !data.scheduler.cancelled.contains(&task.id) {
+                task.finished = task.kind.resume(data.reborrow());
+            }
+        }
+
+        let scheduler = data.scheduler;
+        tasks.retain(|task| !task.finished && !scheduler.cancelled.contains(&task.id));
+
+        // Tasks may have scheduled more tasks
+        tasks.append(&mut scheduler.tasks);
+        scheduler.tasks = tasks;
+        scheduler.cancelled.clear();
+        scheduler.anchor();
+    }
+
+    // Timers are due relative to the first time the scheduler runs after they're scheduled,
+    // so that timers scheduled before the clock advances aren't due a tick early
+    fn anchor(&mut self) {
+        for task in self.tasks.iter_mut() {
+            if let TaskKind::Timer { delay, due: due @ None, .. } = &mut task.kind {
+                *due = Some(self.now + *delay);
+            }
+        }
+    }
+}
+
+impl TaskKind {
+    // Returns true once the task has finished
+    fn resume(&mut self, mut data: GameData<'_, 'static>) -> bool {
+        match self {
+            Self::Timer { due: Some(due), interval, callback, .. } => {
+                if data.scheduler.now < *due {
+                    return false;
+                }
+
+                callback(data.reborrow());
+
+                match interval {
+                    Some(interval) => {
+                        *due += *interval;
+                        false
+                    },
+                    None => true
+                }
+            },
+            Self::Timer { due: None, .. } => false,
+            Self::Coroutine(coroutine) => coroutine.resume(data)
+        }
+    }
+}
+
+enum Step {
+    Run(Box<dyn FnOnce(GameData<'_, 'static>)>),
+    WaitTicks(u64),
+    Wait(f64),
+    WaitUntil(Condition),
+    // Waits are converted into deadlines once they're reached
+    TickDeadline(u64),
+    TimeDeadline(f64)
+}
+
+// A sequence of steps that is spread over multiple ticks, e.g.
+// `Coroutine::new().then(spawn_wave).wait(Duration::from_secs(10)).then(spawn_wave)`.
+// Each tick, steps are run until one of them has to wait
+#[derive(Default)]
+pub struct Coroutine {
+    steps: VecDeque<Step>
+}
+
+impl Coroutine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then<F>(mut self, step: F) -> Self
+        where F: FnOnce(GameData<'_, 'static>) + 'static {
+
+        self.steps.push_back(Step::Run(Box::new(step)));
+        self
+    }
+
+    // Ticks that the World spends paused aren't counted
+    pub fn wait_ticks(mut self, ticks: u64) -> Self {
+        self.steps.push_back(Step::WaitTicks(ticks));
+        self
+    }
+
+    // Waits for the given amount of simulated time
+    pub fn wait(mut self, duration: time::Duration) -> Self {
+        self.steps.push_back(Step::Wait(duration.as_secs_f64()));
+        self
+    }
+
+    // The condition is checked once per tick (even while the World is paused),
+    // starting with the tick that the step is reached
+    pub fn wait_until<F>(mut self, condition: F) -> Self
+        where F: FnMut(&GameData<'_, 'static>) -> bool + 'static {
+
+        self.steps.push_back(Step::WaitUntil(Box::new(condition)));
+        self
+    }
+
+    fn resume(&mut self, mut data: GameData<'_, 'static>) -> bool {
+        while let Some(step) = self.steps.front_mut() {
+            let ready = match step {
+                Step::Run(..) => true,
+                Step::WaitTicks(ticks) => {
+                    *step = Step::TickDeadline(data.scheduler.ticks + *ticks);
+                    continue;
+                },
+                Step::Wait(duration) => {
+                    *step = Step::TimeDeadline(data.scheduler.now + *duration);
+                    continue;
+                },
+                Step::WaitUntil(condition) => condition(&data),
+                Step::TickDeadline(deadline) => data.scheduler.ticks >= *deadline,
+                Step::TimeDeadline(deadline) => data.scheduler.now >= *deadline
+            };
+
+            if !ready {
+                return false;
+            }
+
+            if let Some(Step::Run(step)) = self.steps.pop_front() {
+                step(data.reborrow());
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ cell::{ Cell, RefCell }, rc::Rc, time::Duration };
+
+    use crate::{
+        camera::Camera,
+        world::{ PhysicsConfig, World },
+        GameData
+    };
+
+    use super::{ Coroutine, Scheduler, TaskId };
+
+    // A timestep that adds up exactly
+    const TIMESTEP: f32 = 0.25;
+
+    struct Game {
+        world: World<'static>,
+        camera: Camera,
+        scheduler: Scheduler,
+        tick: u64
+    }
+
+    impl Game {
+        fn new() -> Self {
+            let mut world = World::default();
+            world.set_physics_config(PhysicsConfig { timestep: TIMESTEP, ..PhysicsConfig::default() } );
+
+            Self { world, camera: Camera::default(), scheduler: Scheduler::default(), tick: 0 }
+        }
+
+        fn data(&mut self) -> GameData<'_, 'static> {
+            GameData {
+                world: &mut self.world,
+                camera: &mut self.camera,
+                scheduler: &mut self.scheduler,
+                dt: TIMESTEP,
+                tick: self.tick
+            }
+        }
+
+        // The parts of State::step that the scheduler depends on
+        fn step(&mut self, game_update: impl FnOnce(GameData)) {
+            game_update(self.data());
+            self.world.advance_clock();
+            Scheduler::run(self.data());
+            self.tick += 1;
+        }
+
+        fn steps(&mut self, count: usize) {
+            for _ in 0..count {
+                self.step(|_| ());
+            }
+        }
+    }
+
+    fn ticks(times: f32) -> Duration {
+        Duration::from_secs_f32(TIMESTEP * times)
+    }
+
+    // Records the ticks that the callback runs during
+    fn recorder() -> (Rc<RefCell<Vec<u64>>>, impl FnMut(GameData) + Clone) {
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let callback = {
+            let recorded = recorded.clone();
+            move |data: GameData| recorded.borrow_mut().push(data.tick)
+        };
+
+        (recorded, callback)
+    }
+
+    #[test]
+    fn after_counts_from_the_tick_it_is_scheduled_in() {
+        let mut game = Game::new();
+        let (recorded, callback) = recorder();
+
+        let mut record = callback.clone();
+        game.step(|data| {
+            data.scheduler.after(ticks(2.0), move |mut data| {
+                record(data.reborrow());
+                data.scheduler.after(ticks(1.0), callback);
+            } );
+        } );
+        game.steps(5);
+
+        assert_eq!(*recorded.borrow(), [2, 3]);
+    }
+
+    #[test]
+    fn every_repeats_at_its_interval() {
+        let mut game = Game::new();
+        let (recorded, callback) = recorder();
+
+        game.step(|data| { data.scheduler.every(ticks(2.0), callback); } );
+        game.steps(6);
+
+        assert_eq!(*recorded.borrow(), [2, 4, 6]);
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let mut game = Game::new();
+        let (recorded, callback) = recorder();
+
+        game.step(|data| {
+            data.scheduler.after(ticks(2.0), callback);
+            data.world.set_paused(true);
+        } );
+        game.steps(3);
+        game.world.set_paused(false);
+        game.steps(3);
+
+        assert_eq!(*recorded.borrow(), [5]);
+    }
+
+    #[test]
+    fn cancelled_tasks_do_not_run() {
+        let mut game = Game::new();
+        let (recorded, callback) = recorder();
+
+        let mut timer = None;
+        let mut coroutine = None;
+        game.step(|data| {
+            timer = Some(data.scheduler.after(ticks(2.0), callback.clone()));
+            coroutine = Some(data.scheduler.start(Coroutine::new().wait_ticks(2).then(callback)));
+        } );
+        game.step(|data| {
+            data.scheduler.cancel(timer.unwrap());
+            data.scheduler.cancel(coroutine.unwrap());
+        } );
+        game.steps(4);
+
+        assert!(recorded.borrow().is_empty());
+    }
+
+    #[test]
+    fn task_can_cancel_itself() {
+        let mut game = Game::new();
+        let (recorded, mut callback) = recorder();
+
+        let id: Rc<Cell<Option<TaskId>>> = Rc::default();
+        game.step(|data| {
+            let own_id = id.clone();
+            id.set(Some(data.scheduler.every(ticks(1.0), move |mut data| {
+                callback(data.reborrow());
+                data.scheduler.cancel(own_id.get().unwrap());
+            } )));
+        } );
+        game.steps(4);
+
+        assert_eq!(*recorded.borrow(), [1]);
+    }
+}
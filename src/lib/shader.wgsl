@@ -7,8 +7,11 @@ struct CameraUniform {
 var<uniform> camera: CameraUniform;
 
 struct LightUniform {
+    // w holds the light's radius of influence
     position: vec4<f32>,
-    color: vec4<f32>
+    color: vec4<f32>,
+    // x holds the row of the light's shadow maps in the atlas (-1 if it has none)
+    shadow: vec4<i32>
 }
 
 struct LightSources {
@@ -18,12 +21,62 @@ struct LightSources {
 @group(1) @binding(0) 
 var<storage, read> light_sources: LightSources;
 
+// Lights are assigned to square tiles of the screen
+struct LightGrid {
+    tile_size: u32,
+    columns: u32,
+    rows: u32,
+    light_count: u32
+}
+
+@group(1) @binding(1)
+var<uniform> light_grid: LightGrid;
+
+// The offset and length of each tile's slice of light_indices
+struct LightTiles {
+    ranges: array<vec2<u32>>
+}
+
+@group(1) @binding(2)
+var<storage, read> light_tiles: LightTiles;
+
+struct LightIndices {
+    indices: array<u32>
+}
+
+@group(1) @binding(3)
+var<storage, read> light_indices: LightIndices;
+
+// One row of 6 faces per shadow casting light, each texel holds
+// the distance to the closest occluder relative to the light's radius
+@group(2) @binding(0)
+var shadow_atlas: texture_2d<f32>;
+
+// The view projection matrix of each face in the shadow atlas
+struct ShadowProjections {
+    projections: array<mat4x4<f32>>
+}
+
+@group(2) @binding(1)
+var<storage, read> shadow_projections: ShadowProjections;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
     @location(2) normal: vec3<f32>
 };
 
+// The instance's model matrix, followed by the matrix that transforms its normals
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+    @location(7) normal_0: vec3<f32>,
+    @location(8) normal_1: vec3<f32>,
+    @location(9) normal_2: vec3<f32>
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
@@ -32,22 +85,69 @@ struct VertexOutput {
 };
 
 @vertex
-fn vs_main(model: VertexInput) -> VertexOutput {
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let transform = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let normal_transform = mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+    let world_position = transform * vec4<f32>(model.position, 1.0);
+
     var out: VertexOutput;
-    out.clip_position = camera.projection * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.projection * world_position;
     out.color = model.color;
-    out.world_normal = model.normal;
-    out.world_position = model.position;
+    out.world_normal = normalize(normal_transform * model.normal);
+    out.world_position = world_position.xyz;
     
     return out;
 }
 
+// Returns the fraction of the light that reaches the given position
+fn shadow_factor(light: LightUniform, world_position: vec3<f32>, world_normal: vec3<f32>) -> f32 {
+    if(light.shadow.x < 0) {
+        return 1.0;
+    }
+
+    // Each face of the cube map occupies a square region of the atlas, 6 to a row
+    let face_size = textureDimensions(shadow_atlas).x / 6;
+
+    // Nudge the sample along the normal to avoid self-shadowing
+    // Shadow map texels grow with distance, so the offset does as well
+    let texel_size = distance(world_position, light.position.xyz) / f32(face_size);
+    let position = world_position + world_normal * (0.02 + texel_size * 2.0);
+
+    // Faces are ordered +X, -X, +Y, -Y, +Z, -Z
+    let offset = position - light.position.xyz;
+    let magnitude = abs(offset);
+
+    var face = 0;
+    if(magnitude.x >= magnitude.y && magnitude.x >= magnitude.z) {
+        face = select(1, 0, offset.x > 0.0);
+    } else if(magnitude.y >= magnitude.z) {
+        face = select(3, 2, offset.y > 0.0);
+    } else {
+        face = select(5, 4, offset.z > 0.0);
+    }
+
+    let clip = shadow_projections.projections[light.shadow.x * 6 + face] * vec4<f32>(position, 1.0);
+    let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+
+    // Locate the texel within the face's region of the atlas
+    let texel = clamp(vec2<i32>(uv * f32(face_size)), vec2<i32>(0), vec2<i32>(face_size - 1));
+    let occluder = textureLoad(shadow_atlas, texel + vec2<i32>(face, light.shadow.x) * face_size, 0).r;
+
+    return select(0.0, 1.0, length(offset) / light.position.w <= occluder);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var result = vec3<f32>(0.0, 0.0, 0.0);
 
-    let length = i32(arrayLength(&light_sources.light_uniforms));
-    for(var i: i32 = 0; i < length; i = i + 1) {
+    let tile = min(
+        vec2<u32>(in.clip_position.xy) / light_grid.tile_size,
+        vec2<u32>(light_grid.columns - 1u, light_grid.rows - 1u)
+    );
+
+    let range = light_tiles.ranges[tile.y * light_grid.columns + tile.x];
+    for(var j: u32 = 0u; j < range.y; j = j + 1u) {
+        let i = light_indices.indices[range.x + j];
         if(light_sources.light_uniforms[i].color.a != 0.0) {
             let ambient_color = light_sources.light_uniforms[i].color.xyz * light_sources.light_uniforms[i].color.a;
             let light_dir = normalize(light_sources.light_uniforms[i].position.xyz - in.world_position);
@@ -60,7 +160,9 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
             let dist_sq: f32 = pow(distance(light_sources.light_uniforms[i].position.xyz, in.world_position), 4.0);
 
-            result += (ambient_color + diffuse_color + specular_color) / dist_sq * light_sources.light_uniforms[i].color.a;
+            let shadow = shadow_factor(light_sources.light_uniforms[i], in.world_position, in.world_normal);
+
+            result += (ambient_color + (diffuse_color + specular_color) * shadow) / dist_sq * light_sources.light_uniforms[i].color.a;
             
         }
     }
@@ -0,0 +1,414 @@
+use std::ops::Range;
+
+use cgmath::{
+    Matrix4,
+    Point3,
+    Vector3
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera,
+    light,
+    world,
+    Vertex,
+    instance::Instance
+};
+
+// The maximum number of lights that can cast shadows at once.
+// When more lights request shadows, those closest to the camera are chosen
+pub(crate) const MAX_SHADOW_CASTERS: usize = 4;
+
+// The width and height of each face of a light's shadow cube
+pub(crate) const SHADOW_MAP_SIZE: u32 = 256;
+
+// Each shadow casting light renders the scene in all 6 directions
+pub(crate) const SHADOW_FACES: usize = 6;
+
+const SHADOW_NEAR: f32 = 0.05;
+
+// Offsets of the shadow pass uniform must respect this alignment
+const UNIFORM_STRIDE: wgpu::BufferAddress = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowPassUniform {
+    projection: [[f32; 4]; 4],
+    // The w component holds the light's radius of influence
+    light_position: [f32; 4]
+}
+
+// Omnidirectional shadow maps, packed into a single atlas texture.
+// Each row of the atlas belongs to one light, each column to one face of its shadow cube.
+// Faces are ordered +X, -X, +Y, -Y, +Z, -Z (matching the face selection in shadow_factor).
+// Each texel holds the distance to the closest occluder, relative to the light's radius
+pub(crate) struct ShadowMaps {
+    pub(crate) atlas_view: wgpu::TextureView,
+    pub(crate) depth_texture_view: wgpu::TextureView,
+    pub(crate) projection_buffer: wgpu::Buffer,
+    pub(crate) pass_buffer: wgpu::Buffer,
+    pub(crate) pass_bind_group: wgpu::BindGroup,
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) bind_group: wgpu::BindGroup,
+    pub(crate) pipeline: wgpu::RenderPipeline,
+    // The number of lights whose shadow maps are currently in use
+    pub(crate) caster_count: usize
+}
+
+impl ShadowMaps {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+    const ATLAS_SIZE: wgpu::Extent3d = wgpu::Extent3d {
+        width: SHADOW_MAP_SIZE * SHADOW_FACES as u32,
+        height: SHADOW_MAP_SIZE * MAX_SHADOW_CASTERS as u32,
+        depth_or_array_layers: 1
+    };
+
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let face_count = MAX_SHADOW_CASTERS * SHADOW_FACES;
+
+        let atlas_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: Self::ATLAS_SIZE,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+            } )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let depth_texture_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: Self::ATLAS_SIZE,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Depth32Float,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            } )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // The view projection matrix of every face, used to sample the shadow maps
+        let projection_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(
+                    &vec![[[0.0f32; 4]; 4]; face_count]
+                ),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+            }
+        );
+
+        // 32-bit float textures can't be filtered, so texels are loaded directly
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false
+                        },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    }
+                ],
+                label: None
+            }
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas_view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: projection_buffer.as_entire_binding()
+                }
+            ],
+            label: None
+        } );
+
+        // The shadow pass itself uses a dynamic offset to select the current face
+        let pass_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: UNIFORM_STRIDE * face_count as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        } );
+
+        let pass_uniform_size = wgpu::BufferSize::new(
+            std::mem::size_of::<ShadowPassUniform>() as u64
+        );
+
+        let pass_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX
+                            | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: pass_uniform_size
+                        },
+                        count: None
+                    }
+                ],
+                label: None
+            }
+        );
+
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pass_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &pass_buffer,
+                        offset: 0,
+                        size: pass_uniform_size
+                    } )
+                }
+            ],
+            label: None
+        } );
+
+        let shader = device.create_shader_module(
+            wgpu::include_wgsl!("shadow.wgsl")
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&pass_bind_group_layout],
+                push_constant_ranges: &[]
+            }
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    Vertex::description(),
+                    Instance::description()
+                ]
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: Self::FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL
+                    } )
+                ]
+            } ),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Back faces are culled so that lights inside of emissive tiles
+                // aren't occluded by the tile that contains them
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            } ),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        } );
+
+        Self {
+            atlas_view,
+            depth_texture_view,
+            projection_buffer,
+            pass_buffer,
+            pass_bind_group,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            caster_count: 0
+        }
+    }
+
+    // Chooses which lights cast shadows this frame and uploads their projections
+    pub(crate) fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        light_sources: &mut light::LightSources,
+        camera: &camera::Camera
+    ) {
+        let light_position = |light: &light::Light| {
+            Point3::new(light.position[0], light.position[1], light.position[2])
+        };
+
+        // Prefer the lights closest to the camera
+        let mut casters = light_sources.shadow_casters.clone();
+        casters.sort_by(|&a, &b| {
+            let distance = |index: usize| cgmath::MetricSpace::distance2(
+                light_position(&light_sources.lights[index]),
+                camera.eye
+            );
+
+            distance(a).total_cmp(&distance(b))
+        } );
+        casters.truncate(MAX_SHADOW_CASTERS);
+
+        let mut projections = vec![[[0.0f32; 4]; 4]; MAX_SHADOW_CASTERS * SHADOW_FACES];
+        for (slot, &index) in casters.iter().enumerate() {
+            let light = &mut light_sources.lights[index];
+            let matrices = face_matrices(light_position(light), light.position[3]);
+
+            for (face, matrix) in matrices.iter().enumerate() {
+                let face = slot * SHADOW_FACES + face;
+                projections[face] = (*matrix).into();
+
+                queue.write_buffer(
+                    &self.pass_buffer,
+                    face as wgpu::BufferAddress * UNIFORM_STRIDE,
+                    bytemuck::cast_slice(&[ShadowPassUniform {
+                        projection: projections[face],
+                        light_position: light.position
+                    } ])
+                );
+            }
+
+            light.shadow[0] = slot as i32;
+        }
+
+        queue.write_buffer(
+            &self.projection_buffer,
+            0,
+            bytemuck::cast_slice(&projections)
+        );
+
+        self.caster_count = casters.len();
+    }
+
+    // Renders the light distance of the given geometry into each active shadow map
+    // Each piece of geometry is drawn with the given range of instances
+    pub(crate) fn render<'a, I>(
+        &self, 
+        encoder: &mut wgpu::CommandEncoder, 
+        instance_buffer: &wgpu::Buffer,
+        geometry: I
+    ) where I: Iterator<Item = (&'a world::GeometryBuffers, Range<u32>)> + Clone {
+
+        if self.caster_count == 0 { return; }
+
+        let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: &self.atlas_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Nothing occludes the light within its radius
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true
+                    }
+                } )
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false
+                } ),
+                stencil_ops: None
+            } )
+        } );
+
+        shadow_pass.set_pipeline(&self.pipeline);
+        shadow_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+        for face in 0..(self.caster_count * SHADOW_FACES) {
+            // Restrict rendering to the face's region of the atlas
+            let (x, y) = (
+                (face % SHADOW_FACES) as u32 * SHADOW_MAP_SIZE,
+                (face / SHADOW_FACES) as u32 * SHADOW_MAP_SIZE
+            );
+
+            shadow_pass.set_viewport(
+                x as f32,
+                y as f32,
+                SHADOW_MAP_SIZE as f32,
+                SHADOW_MAP_SIZE as f32,
+                0.0,
+                1.0
+            );
+            shadow_pass.set_scissor_rect(x, y, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+
+            shadow_pass.set_bind_group(
+                0,
+                &self.pass_bind_group,
+                &[(face as wgpu::BufferAddress * UNIFORM_STRIDE) as u32]
+            );
+
+            for (buffers, instances) in geometry.clone() {
+                shadow_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+                shadow_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                shadow_pass.draw_indexed(0..buffers.index_count, 0, instances);
+            }
+        }
+    }
+}
+
+// Builds the view projection matrix of each face of a light's shadow cube
+fn face_matrices(position: Point3<f32>, radius: f32) -> [Matrix4<f32>; SHADOW_FACES] {
+    // The corners of each face are further away than its center
+    let far = radius.max(SHADOW_NEAR * 2.0) * 3f32.sqrt();
+
+    let projection = cgmath::perspective(
+        cgmath::Deg(90.0),
+        1.0,
+        SHADOW_NEAR,
+        far
+    );
+
+    let faces = [
+        (Vector3::unit_x(), -Vector3::unit_y()),
+        (-Vector3::unit_x(), -Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), -Vector3::unit_z()),
+        (Vector3::unit_z(), -Vector3::unit_y()),
+        (-Vector3::unit_z(), -Vector3::unit_y())
+    ];
+
+    faces.map(|(direction, up)| {
+        let view = Matrix4::look_at_rh(position, position + direction, up);
+        camera::Camera::MATRIX_CORRECTION_FOR_WGPU * projection * view
+    } )
+}
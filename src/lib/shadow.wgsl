@@ -0,0 +1,49 @@
+struct ShadowPass {
+    projection: mat4x4<f32>,
+    // w holds the light's radius of influence
+    light_position: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> shadow_pass: ShadowPass;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>
+};
+
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+    @location(7) normal_0: vec3<f32>,
+    @location(8) normal_1: vec3<f32>,
+    @location(9) normal_2: vec3<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>
+};
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let transform = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = transform * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = shadow_pass.projection * world_position;
+    out.world_position = world_position.xyz;
+
+    return out;
+}
+
+// Stores the distance to the light, relative to its radius
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light_distance = distance(in.world_position, shadow_pass.light_position.xyz);
+
+    return vec4<f32>(light_distance / shadow_pass.light_position.w, 0.0, 0.0, 1.0);
+}
@@ -4,40 +4,64 @@ use wgpu::util::DeviceExt;
 
 use crate::{
     camera,
+    Config,
+    EngineError,
+    GameData,
+    Scheduler,
     Vertex,
+    instance::Instance,
     world,
     light,
+    shadow
 };
 
+// Where the frames produced by the State end up
+pub(crate) enum RenderTarget {
+    Surface(wgpu::Surface),
+    Texture(wgpu::Texture)
+}
+
 pub(crate) struct State {
     pub world: world::World<'static>,
+    pub(crate) scheduler: Scheduler,
+
+    // The number of ticks simulated so far
+    pub(crate) tick: u64,
 
     pub(crate) size: winit::dpi::PhysicalSize<u32>,
-    pub(crate) surface: wgpu::Surface,
+    pub(crate) target: RenderTarget,
     pub(crate) device: wgpu::Device,
     pub(crate) queue: wgpu::Queue,
     pub(crate) surface_config: wgpu::SurfaceConfiguration,
-    pub(crate) vertex_buffer: wgpu::Buffer,
-    pub(crate) index_buffer: wgpu::Buffer,
-    pub(crate) index_count: u32,
+    pub(crate) entity_buffers: Option<world::GeometryBuffers>,
+    pub(crate) instance_buffer: GrowableBuffer,
     pub(crate) camera: camera::Camera,
+    // Where the Camera was pointed before the most recent tick
+    pub(crate) previous_camera_target: Option<cgmath::Point3<f32>>,
     pub(crate) camera_uniform: camera::CameraUniform,
     pub(crate) camera_buffer: wgpu::Buffer,
     pub(crate) camera_bind_group: wgpu::BindGroup,
     pub(crate) light_sources: light::LightSources,
-    pub(crate) light_buffer: wgpu::Buffer,
+    pub(crate) light_buffer: GrowableBuffer,
+    pub(crate) light_grid_buffer: wgpu::Buffer,
+    pub(crate) light_tile_buffer: GrowableBuffer,
+    pub(crate) light_index_buffer: GrowableBuffer,
+    pub(crate) light_bind_group_layout: wgpu::BindGroupLayout,
     pub(crate) light_bind_group: wgpu::BindGroup,
+    pub(crate) shadow_maps: shadow::ShadowMaps,
+    pub(crate) clear_color: wgpu::Color,
+    pub(crate) sample_count: u32,
+    // Frames are rendered here and resolved into the target when MSAA is enabled
+    pub(crate) msaa_texture_view: Option<wgpu::TextureView>,
     pub(crate) depth_texture_view: wgpu::TextureView,
     pub(crate) render_pipeline: wgpu::RenderPipeline
 }
 
 impl State {
-    pub async fn new(window: &window::Window) -> Self {
-        let world = world::World::default();
-
+    pub async fn new(window: &window::Window, config: &Config) -> Result<Self, EngineError> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let instance = wgpu::Instance::new(config.backends);
 
         let surface = unsafe { 
             instance.create_surface(window) 
@@ -45,54 +69,113 @@ impl State {
 
         let adapter = instance.request_adapter(
             &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: config.power_preference,
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false
             },
-        ).await.unwrap();
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: { 
-                    if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    }
-                },
-                label: None
-            },
-            None
-        ).await.unwrap();
+        ).await.ok_or(EngineError::NoAdapter)?;
+
+        let (device, queue) = request_device(&adapter).await?;
+
+        let format = surface.get_supported_formats(&adapter)
+            .first()
+            .copied()
+            .ok_or(EngineError::UnsupportedSurfaceFormat)?;
+
+        // Fifo is supported everywhere
+        let present_mode = match surface.get_supported_modes(&adapter) {
+            modes if modes.contains(&config.present_mode) => config.present_mode,
+            _ => wgpu::PresentMode::Fifo
+        };
 
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
+            format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo
+            present_mode
         };
 
         surface.configure(&device, &surface_config);
 
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: &[],
-                usage: wgpu::BufferUsages::VERTEX
-            }
-        );
+        let sample_count = supported_sample_count(&adapter, surface_config.format, config.msaa_samples);
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: &[],
-                usage: wgpu::BufferUsages::INDEX,
+        Ok(Self::with_target(
+            RenderTarget::Surface(surface), 
+            device, 
+            queue, 
+            surface_config,
+            config.clear_color,
+            sample_count
+        ))
+    }
+
+    // Renders into an offscreen texture instead of a window surface.
+    // Prefers a software (fallback) adapter so that it works on machines without a GPU
+    pub async fn new_headless(
+        size: winit::dpi::PhysicalSize<u32>, 
+        config: &Config
+    ) -> Result<Self, EngineError> {
+        let instance = wgpu::Instance::new(config.backends);
+
+        let mut adapter_options = wgpu::RequestAdapterOptions {
+            power_preference: config.power_preference,
+            compatible_surface: None,
+            force_fallback_adapter: true
+        };
+
+        let adapter = match instance.request_adapter(&adapter_options).await {
+            Some(adapter) => adapter,
+            None => {
+                adapter_options.force_fallback_adapter = false;
+                instance.request_adapter(&adapter_options)
+                    .await
+                    .ok_or(EngineError::NoAdapter)?
             }
+        };
+
+        let (device, queue) = request_device(&adapter).await?;
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT 
+                | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::Fifo
+        };
+
+        let texture = create_target_texture(&device, &surface_config);
+
+        let sample_count = supported_sample_count(&adapter, surface_config.format, config.msaa_samples);
+
+        Ok(Self::with_target(
+            RenderTarget::Texture(texture), 
+            device, 
+            queue, 
+            surface_config,
+            config.clear_color,
+            sample_count
+        ))
+    }
+
+    fn with_target(
+        target: RenderTarget,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface_config: wgpu::SurfaceConfiguration,
+        clear_color: wgpu::Color,
+        sample_count: u32
+    ) -> Self {
+        let world = world::World::default();
+
+        let size = winit::dpi::PhysicalSize::new(
+            surface_config.width, 
+            surface_config.height
         );
 
-        let index_count = 0u32;
+        let entity_buffers = None;
+        let instance_buffer = GrowableBuffer::new(&device, wgpu::BufferUsages::VERTEX);
 
         let camera = camera::Camera::default();
 
@@ -141,67 +224,84 @@ impl State {
             }
         ) };
 
-        let light_sources = light::LightSources {
-            light_uniforms: [
-                light::Light::default(); 
-                light::MAX_LIGHT_SOURCES
-            ]
-        };
+        let light_sources = light::LightSources::default();
 
-        let light_buffer = device.create_buffer_init(
+        let light_buffer = GrowableBuffer::new(&device, wgpu::BufferUsages::STORAGE);
+        let light_tile_buffer = GrowableBuffer::new(&device, wgpu::BufferUsages::STORAGE);
+        let light_index_buffer = GrowableBuffer::new(&device, wgpu::BufferUsages::STORAGE);
+
+        let light_grid_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&[light_sources]),
+                contents: bytemuck::cast_slice(&[light::LightGridUniform {
+                    tile_size: light::LIGHT_TILE_SIZE,
+                    columns: 1,
+                    rows: 1,
+                    light_count: 0
+                } ]),
                 usage: { 
-                    wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+                    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
                 },
             }
         );
 
         let light_bind_group_layout = { 
+            let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
+                    storage_entry(0),
                     wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX 
-                            | wgpu::ShaderStages::FRAGMENT,
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
                             min_binding_size: None,
                         },
                         count: None,
-                    }
+                    },
+                    storage_entry(2),
+                    storage_entry(3)
                 ],
                 label: None
             }
         ) };
 
-        let light_bind_group = { 
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &light_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: light_buffer.as_entire_binding(),
-                    }
-                ],
-                label: None
-            }
-        ) };
+        let light_bind_group = create_light_bind_group(
+            &device,
+            &light_bind_group_layout,
+            &light_buffer,
+            &light_grid_buffer,
+            &light_tile_buffer,
+            &light_index_buffer
+        );
+
+        let shadow_maps = shadow::ShadowMaps::new(&device);
 
         let shader = device.create_shader_module(
             wgpu::include_wgsl!("shader.wgsl")
         );    
 
-        let depth_texture_view = create_depth_texture(&device, &surface_config);
+        let depth_texture_view = create_depth_texture(&device, &surface_config, sample_count);
+        let msaa_texture_view = create_msaa_texture(&device, &surface_config, sample_count);
 
         let render_pipeline_layout = device.create_pipeline_layout(
             &wgpu::PipelineLayoutDescriptor {
                 label: None,
                 bind_group_layouts: &[
                     &camera_bind_group_layout,
-                    &light_bind_group_layout
+                    &light_bind_group_layout,
+                    &shadow_maps.bind_group_layout
                 ],
                 push_constant_ranges: &[]
             }
@@ -215,7 +315,8 @@ impl State {
                     module: &shader,
                     entry_point: "vs_main",
                     buffers: &[
-                        Vertex::description()
+                        Vertex::description(),
+                        Instance::description()
                     ]
                 },
                 fragment: Some(
@@ -248,7 +349,7 @@ impl State {
                     bias: wgpu::DepthBiasState::default()
                 } ),
                 multisample: wgpu::MultisampleState {
-                    count: 1,
+                    count: sample_count,
                     mask: !0,
                     alpha_to_coverage_enabled: false,
                 },
@@ -258,21 +359,31 @@ impl State {
 
         Self {
             world,
+            scheduler: Scheduler::default(),
+            tick: 0,
             size,
-            surface,
+            target,
             device,
             queue,
             surface_config,
-            vertex_buffer,
-            index_buffer,
-            index_count,
+            entity_buffers,
+            instance_buffer,
             camera,
+            previous_camera_target: None,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
             light_sources,
             light_buffer,
+            light_grid_buffer,
+            light_tile_buffer,
+            light_index_buffer,
+            light_bind_group_layout,
             light_bind_group,
+            shadow_maps,
+            clear_color,
+            sample_count,
+            msaa_texture_view,
             depth_texture_view,
             render_pipeline
         }
@@ -286,27 +397,132 @@ impl State {
 
             self.depth_texture_view = create_depth_texture(
                 &self.device, 
-                &self.surface_config
+                &self.surface_config,
+                self.sample_count
             );
 
-            self.surface.configure(&self.device, &self.surface_config);
+            self.msaa_texture_view = create_msaa_texture(
+                &self.device, 
+                &self.surface_config,
+                self.sample_count
+            );
+
+            match &mut self.target {
+                RenderTarget::Surface(surface) => {
+                    surface.configure(&self.device, &self.surface_config)
+                },
+                RenderTarget::Texture(texture) => {
+                    *texture = create_target_texture(
+                        &self.device, 
+                        &self.surface_config
+                    )
+                }
+            }
+        }
+    }
+
+    pub(crate) fn game_data(&mut self) -> GameData<'_, 'static> {
+        let dt = self.world.physics_config().timestep;
+
+        GameData { 
+            world: &mut self.world, 
+            camera: &mut self.camera,
+            scheduler: &mut self.scheduler,
+            dt,
+            tick: self.tick
         }
     }
 
-    pub(crate) fn update(&mut self) {
+    // Runs the game's update, then advances the simulation by a single tick.
+    // Scheduled tasks are run first, then the World's systems on either side of the physics step.
+    // The state from before the tick is kept so that frames can be interpolated
+    pub(crate) fn step(&mut self, game_update: impl FnOnce(GameData)) {
+        self.previous_camera_target = Some(self.camera.target);
+
+        game_update(self.game_data());
+
+        self.world.store_entity_centers();
+        self.world.advance_clock();
+        Scheduler::run(self.game_data());
+        self.world.run_systems(world::SystemStage::BeforePhysics);
         self.world.resolve_entity_lifetimes();
         self.world.resolve_entity_physics();
-        
-       (self.vertex_buffer, self.index_buffer, self.index_count) = self.world.build_geometry_buffers(&mut self.device);
-       (self.light_sources, ..) = self.world.build_light_sources();
+        self.world.resolve_attachments();
+        self.world.run_systems(world::SystemStage::AfterPhysics);
+
+        self.camera.follow_entity(&self.world);
+
+        self.tick += 1;
+    }
+
+    // Uploads the current state of the World and Camera to the GPU.
+    // Entities and the Camera's target are drawn `alpha` of the way between
+    // where they were before the most recent tick (0.0) and where they are now (1.0)
+    pub(crate) fn prepare(&mut self, alpha: f32) {
+        let camera = match self.previous_camera_target {
+            Some(previous_target) => self.camera.interpolate(previous_target, alpha),
+            None => self.camera.clone()
+        };
+
+        self.world.build_chunk_buffers(&self.device);
+
+        self.entity_buffers = self.world.build_entity_buffers(&self.device, alpha);
+
+        let instances = self.world.build_entity_instances(&self.device, alpha);
+        self.instance_buffer.write(
+            &self.device, 
+            &self.queue, 
+            bytemuck::cast_slice(&instances)
+        );
+
+        self.light_sources = self.world.build_light_sources(alpha);
+
+        self.shadow_maps.prepare(&self.queue, &mut self.light_sources, &camera);
+
+        let light_grid = light::LightGrid::build(
+            &self.light_sources,
+            camera.build_view_projection_matrix(),
+            self.surface_config.width,
+            self.surface_config.height
+        );
 
         self.queue.write_buffer(
-            &self.light_buffer, 
+            &self.light_grid_buffer, 
             0, 
-            bytemuck::cast_slice(&[self.light_sources])
+            bytemuck::cast_slice(&[light_grid.uniform])
         );
 
-        self.camera_uniform.update_projection(&self.camera);
+        // Growing any of the storage buffers invalidates the bind group
+        let reallocated = [
+            self.light_buffer.write(
+                &self.device, 
+                &self.queue, 
+                bytemuck::cast_slice(&self.light_sources.lights)
+            ),
+            self.light_tile_buffer.write(
+                &self.device, 
+                &self.queue, 
+                bytemuck::cast_slice(&light_grid.tiles)
+            ),
+            self.light_index_buffer.write(
+                &self.device, 
+                &self.queue, 
+                bytemuck::cast_slice(&light_grid.light_indices)
+            )
+        ];
+
+        if reallocated.contains(&true) {
+            self.light_bind_group = create_light_bind_group(
+                &self.device,
+                &self.light_bind_group_layout,
+                &self.light_buffer,
+                &self.light_grid_buffer,
+                &self.light_tile_buffer,
+                &self.light_index_buffer
+            );
+        }
+
+        self.camera_uniform.update_projection(&camera);
         self.queue.write_buffer(
             &self.camera_buffer, 
             0, 
@@ -315,17 +531,48 @@ impl State {
     }
 
     pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
+        match &self.target {
+            RenderTarget::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default()
+                );
+
+                self.draw(&view);
+
+                output.present();
+            },
+            RenderTarget::Texture(texture) => {
+                let view = texture.create_view(
+                    &wgpu::TextureViewDescriptor::default()
+                );
+
+                self.draw(&view);
+            }
+        }
 
+        Ok(())
+    }
+
+    fn draw(&self, view: &wgpu::TextureView) {
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
                 label: None,
             }
         );
 
+        // Each chunk owns its own geometry and is drawn separately,
+        // followed by the combined geometry of the entities.
+        // Both are already in world space, so they use the identity instance.
+        // Each Mesh is then drawn once for all of the entities that share it
+        let geometry = self.world
+            .chunk_buffers()
+            .chain(self.entity_buffers.as_ref())
+            .map(|buffers| (buffers, 0..1))
+            .chain(self.world.mesh_buffers());
+
+        self.shadow_maps.render(&mut encoder, &self.instance_buffer.buffer, geometry.clone());
+
         {
             let mut render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
@@ -333,11 +580,11 @@ impl State {
                     color_attachments: &[
                         Some(
                             wgpu::RenderPassColorAttachment {
-                                view: &view,
-                                resolve_target: None,
+                                view: self.msaa_texture_view.as_ref().unwrap_or(view),
+                                resolve_target: self.msaa_texture_view.as_ref().map(|_| view),
                                 ops: wgpu::Operations {
                                     load: wgpu::LoadOp::Clear(
-                                        wgpu::Color::BLACK
+                                        self.clear_color
                                     ),
                                     store: true
                                 },
@@ -358,34 +605,216 @@ impl State {
             // Set render pipeline
             render_pass.set_pipeline(&self.render_pipeline);
 
-            // Camera and light bind groups
+            // Camera, light and shadow bind groups
             render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
             render_pass.set_bind_group(1, &self.light_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.shadow_maps.bind_group, &[]);
 
-            // Set vertex and index buffers
             render_pass.set_vertex_buffer(
-                0, 
-                self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.index_buffer.slice(..), 
-                wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+                1, 
+                self.instance_buffer.buffer.slice(..));
+
+            // Set vertex and index buffers
+            for (buffers, instances) in geometry {
+                render_pass.set_vertex_buffer(
+                    0, 
+                    buffers.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    buffers.index_buffer.slice(..), 
+                    wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..buffers.index_count, 0, instances);
+            }
         }
     
         self.queue.submit(
             std::iter::once(encoder.finish())
         );
+    }
 
-        output.present();
-    
-        Ok(())
+    // Copies the contents of an offscreen target into tightly packed RGBA rows.
+    // Returns None when the State presents to a window surface
+    pub(crate) fn read_pixels(&self) -> Option<Vec<u8>> {
+        let texture = match &self.target {
+            RenderTarget::Surface(..) => return None,
+            RenderTarget::Texture(texture) => texture
+        };
+
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = {
+            let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+            unpadded_bytes_per_row.div_ceil(alignment) * alignment
+        };
+
+        let output_buffer = self.device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: None,
+                size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST 
+                    | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            }
+        );
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: None,
+            }
+        );
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height)
+                }
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            }
+        );
+
+        self.queue.submit(
+            std::iter::once(encoder.finish())
+        );
+
+        let buffer_slice = output_buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {  });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let pixels = buffer_slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect::<Vec<u8>>();
+
+        output_buffer.unmap();
+
+        Some(pixels)
     }
 }
 
-pub(crate) fn create_depth_texture(
+// A storage (or vertex) buffer that grows to fit whatever is written to it
+pub(crate) struct GrowableBuffer {
+    pub(crate) buffer: wgpu::Buffer,
+    pub(crate) capacity: wgpu::BufferAddress,
+    pub(crate) usage: wgpu::BufferUsages
+}
+
+impl GrowableBuffer {
+    // Bindings can't be empty, so every buffer starts out with a little space
+    const MIN_CAPACITY: wgpu::BufferAddress = 256;
+
+    pub(crate) fn new(device: &wgpu::Device, usage: wgpu::BufferUsages) -> Self {
+        Self::with_capacity(device, usage, Self::MIN_CAPACITY)
+    }
+
+    fn with_capacity(
+        device: &wgpu::Device, 
+        usage: wgpu::BufferUsages, 
+        capacity: wgpu::BufferAddress
+    ) -> Self {
+        let buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: None,
+                size: capacity,
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false
+            }
+        );
+
+        Self { buffer, capacity, usage }
+    }
+
+    // Returns true if the buffer had to be reallocated
+    pub(crate) fn write(
+        &mut self, 
+        device: &wgpu::Device, 
+        queue: &wgpu::Queue, 
+        contents: &[u8]
+    ) -> bool {
+        let size = contents.len() as wgpu::BufferAddress;
+
+        let reallocated = size > self.capacity;
+        if reallocated {
+            *self = Self::with_capacity(device, self.usage, size.next_power_of_two());
+        }
+
+        if !contents.is_empty() {
+            queue.write_buffer(&self.buffer, 0, contents);
+        }
+
+        reallocated
+    }
+}
+
+fn create_light_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    light_buffer: &GrowableBuffer,
+    light_grid_buffer: &wgpu::Buffer,
+    light_tile_buffer: &GrowableBuffer,
+    light_index_buffer: &GrowableBuffer
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: light_grid_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: light_tile_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: light_index_buffer.buffer.as_entire_binding(),
+            }
+        ],
+        label: None
+    } )
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features: wgpu::Features::empty(),
+            limits: { 
+                if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                }
+            },
+            label: None
+        },
+        None
+    ).await
+}
+
+pub(crate) fn create_target_texture(
     device: &wgpu::Device, 
     config: &wgpu::SurfaceConfiguration
-) -> wgpu::TextureView {
+) -> wgpu::Texture {
     let size = wgpu::Extent3d {
         width: config.width,
         height: config.height,
@@ -398,9 +827,85 @@ pub(crate) fn create_depth_texture(
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage
+    };
+
+    device.create_texture(&desc)
+}
+
+// Returns the requested number of samples if the format supports multisampling, otherwise 1
+fn supported_sample_count(
+    adapter: &wgpu::Adapter, 
+    format: wgpu::TextureFormat, 
+    requested: u32
+) -> u32 {
+    let flags = |format| adapter.get_texture_format_features(format).flags;
+
+    // Only the color target is resolved
+    let supported = flags(format).contains(
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE 
+            | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE
+    ) && flags(wgpu::TextureFormat::Depth32Float).contains(
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+    );
+
+    if requested > 1 && requested.is_power_of_two() && supported {
+        requested
+    } else {
+        1
+    }
+}
+
+// Only needed when MSAA is enabled
+pub(crate) fn create_msaa_texture(
+    device: &wgpu::Device, 
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32
+) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+
+    let size = wgpu::Extent3d {
+        width: config.width,
+        height: config.height,
+        depth_or_array_layers: 1
+    };
+
+    let desc = wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+    };
+
+    let texture = device.create_texture(&desc);
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+pub(crate) fn create_depth_texture(
+    device: &wgpu::Device, 
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: config.width,
+        height: config.height,
+        depth_or_array_layers: 1
+    };
+
+    let desc = wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
         format: wgpu::TextureFormat::Depth32Float,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-            | wgpu::TextureUsages::TEXTURE_BINDING
     };
 
     let texture = device.create_texture(&desc);
@@ -0,0 +1,107 @@
+use cgmath::{ Point3, Vector3, Zero };
+
+// An axis-aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self { min: center - half_extents, max: center + half_extents }
+    }
+
+    // The space occupied by the tile at the given position
+    pub fn from_tile(position: Point3<i16>) -> Self {
+        Self::from_center(position.cast::<f32>().unwrap(), Vector3::new(0.5, 0.5, 0.5))
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min + (self.max - self.min) * 0.5
+    }
+
+    pub fn translate(&self, offset: Vector3<f32>) -> Self {
+        Self { min: self.min + offset, max: self.max + offset }
+    }
+
+    // The point within the box that is closest to the given point
+    pub fn closest_point(&self, point: Point3<f32>) -> Point3<f32> {
+        Point3::new(
+            point.x.clamp(self.min.x, self.max.x),
+            point.y.clamp(self.min.y, self.max.y),
+            point.z.clamp(self.min.z, self.max.z)
+        )
+    }
+
+    // Boxes that only share a face don't intersect
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && self.max[axis] > other.min[axis])
+    }
+
+    // Returns the distance along the ray to the box and the normal of the face it enters through.
+    // The direction must be normalized. If the origin lies inside the box, the distance and normal are zero
+    pub fn intersect_ray(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<(f32, Vector3<f32>)> {
+        let mut near = f32::NEG_INFINITY;
+        let mut far = f32::INFINITY;
+        let mut normal = Vector3::zero();
+
+        for axis in 0..3 {
+            let (start, speed) = (origin[axis], direction[axis]);
+
+            if speed == 0.0 {
+                // Parallel rays only intersect if they lie between the slabs
+                if start < self.min[axis] || start > self.max[axis] {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let (mut entry, mut exit) = (
+                (self.min[axis] - start) / speed,
+                (self.max[axis] - start) / speed
+            );
+            if entry > exit {
+                std::mem::swap(&mut entry, &mut exit);
+            }
+
+            if entry > near {
+                near = entry;
+                normal = Vector3::zero();
+                normal[axis] = -speed.signum();
+            }
+
+            far = far.min(exit);
+        }
+
+        if near > far || far < 0.0 {
+            None
+        } else if near < 0.0 {
+            Some((0.0, Vector3::zero()))
+        } else {
+            Some((near, normal))
+        }
+    }
+
+    // The positions of all tiles that intersect the box
+    pub(crate) fn tiles(&self) -> impl Iterator<Item = Point3<i16>> {
+        // Tile p spans [p - 0.5, p + 0.5]
+        let range = |axis: usize| {
+            let lower = (self.min[axis] + 0.5).floor().max(i16::MIN as f32) as i16;
+            let upper = (self.max[axis] - 0.5).ceil().min(i16::MAX as f32) as i16;
+
+            lower..=upper
+        };
+
+        let (xs, ys, zs) = (range(0), range(1), range(2));
+        xs.flat_map(move |x| {
+            let zs = zs.clone();
+            ys.clone().flat_map(move |y| zs.clone().map(move |z| Point3::new(x, y, z)))
+        } )
+    }
+}
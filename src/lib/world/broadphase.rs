@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use cgmath::Point3;
+
+use super::{ Aabb, EntityHandle };
+
+// The width of each cell, in tiles
+const CELL_SIZE: f32 = 2.0;
+
+// Buckets bounding boxes into a uniform grid,
+// so that each box is only tested against the boxes near it
+#[derive(Default)]
+pub(crate) struct SpatialHash {
+    cells: HashMap<Point3<i32>, Vec<usize>>
+}
+
+impl SpatialHash {
+    fn cells(bounds: &Aabb) -> impl Iterator<Item = Point3<i32>> {
+        let cell = |coordinate: f32| (coordinate / CELL_SIZE).floor() as i32;
+
+        let (min, max) = (
+            Point3::new(cell(bounds.min.x), cell(bounds.min.y), cell(bounds.min.z)),
+            Point3::new(cell(bounds.max.x), cell(bounds.max.y), cell(bounds.max.z))
+        );
+
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| Point3::new(x, y, z)))
+        } )
+    }
+
+    pub(crate) fn insert(&mut self, index: usize, bounds: &Aabb) {
+        for cell in Self::cells(bounds) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    // The bounds must be the same as those the index was inserted with
+    pub(crate) fn remove(&mut self, index: usize, bounds: &Aabb) {
+        for cell in Self::cells(bounds) {
+            if let Some(indices) = self.cells.get_mut(&cell) {
+                indices.retain(|i| *i != index);
+            }
+        }
+    }
+
+    // Returns every index that shares a cell with the bounds, in ascending order.
+    // The boxes themselves still have to be tested for intersection
+    pub(crate) fn query(&self, bounds: &Aabb) -> Vec<usize> {
+        let mut indices = Self::cells(bounds)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
+
+// The SpatialHash built by the most recent physics step, kept for World::entities_in_aabb
+// and World::entities_in_radius. Each index refers to the handle at that position
+pub(crate) struct EntityBroadphase {
+    pub(crate) hash: SpatialHash,
+    pub(crate) handles: Vec<EntityHandle>
+}
+
+impl EntityBroadphase {
+    // The entities that could intersect the bounds, in ascending order of index
+    pub(crate) fn query(&self, bounds: &Aabb) -> impl Iterator<Item = EntityHandle> + '_ {
+        self.hash.query(bounds).into_iter().map(|index| self.handles[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use crate::world::Aabb;
+
+    use super::SpatialHash;
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb::new(Point3::from(min), Point3::from(max))
+    }
+
+    #[test]
+    fn queries_return_nearby_indices_once() {
+        let mut hash = SpatialHash::default();
+        // Spans several cells, including negative ones
+        hash.insert(3, &aabb([-3.0, -1.0, 0.0], [3.0, 1.0, 0.5]));
+        hash.insert(1, &aabb([0.5, 0.5, 0.5], [1.0, 1.0, 1.0]));
+        hash.insert(2, &aabb([20.0, 0.0, 0.0], [21.0, 1.0, 1.0]));
+
+        assert_eq!(hash.query(&aabb([-4.0, -4.0, -4.0], [4.0, 4.0, 4.0])), [1, 3]);
+        assert_eq!(hash.query(&aabb([-2.5, 0.0, 0.0], [-2.0, 0.5, 0.5])), [3]);
+        assert!(hash.query(&aabb([19.0, 0.0, 0.0], [19.5, 0.5, 0.5])).is_empty());
+        assert_eq!(hash.query(&aabb([19.0, 0.0, 0.0], [20.5, 0.5, 0.5])), [2]);
+        assert!(hash.query(&aabb([10.0, 10.0, 10.0], [11.0, 11.0, 11.0])).is_empty());
+    }
+
+    #[test]
+    fn removed_indices_are_not_returned() {
+        let mut hash = SpatialHash::default();
+        let bounds = aabb([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+        hash.insert(0, &bounds);
+        hash.insert(1, &bounds);
+
+        hash.remove(0, &bounds);
+        assert_eq!(hash.query(&bounds), [1]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use cgmath::Point3;
+
+use wgpu::Device;
+
+use super::{ Tile, Triangles, drawable::GeometryBuffers };
+
+// The number of tiles along each edge of a chunk
+pub(crate) const CHUNK_SIZE: i16 = 16;
+
+// A fixed-size region of the World that owns its tiles and their GPU geometry.
+// The mesh is only rebuilt when a tile inside of the chunk has changed
+#[derive(Default)]
+pub(crate) struct Chunk {
+    pub(crate) tiles: HashMap<Point3<i16>, Box<dyn Tile>>,
+    pub(crate) buffers: Option<GeometryBuffers>,
+    pub(crate) dirty: bool
+}
+
+impl Chunk {
+    // Returns the coordinates of the chunk that contains the given tile position
+    pub(crate) fn coordinates(position: Point3<i16>) -> Point3<i16> {
+        Point3::new(
+            position.x.div_euclid(CHUNK_SIZE),
+            position.y.div_euclid(CHUNK_SIZE),
+            position.z.div_euclid(CHUNK_SIZE)
+        )
+    }
+
+    // Opaque cubes are skipped, they are meshed by the World (see mesher::greedy_mesh)
+    pub(crate) fn build_object_data(&self) -> Triangles {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for tile in self.tiles.values().filter(|t| !t.is_opaque_cube()) {
+            let mut triangles = tile.build_object_data();
+            let mut offset_indices = triangles.indices
+                .iter()
+                .map(|i| *i + vertices.len() as u32)
+                .collect::<Vec<u32>>();
+
+            indices.append(&mut offset_indices);
+            vertices.append(&mut triangles.vertices);
+        }
+
+        Triangles { vertices, indices }
+    }
+
+    // Replaces the chunk's GPU buffers with the given mesh
+    pub(crate) fn upload(&mut self, device: &Device, triangles: Triangles) {
+        self.buffers = GeometryBuffers::new(device, &triangles);
+
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use crate::world::{ testing::Block, World };
+
+    use super::Chunk;
+
+    // Pretends that every chunk's mesh was rebuilt
+    fn clean(world: &mut World) {
+        for chunk in world.chunks.values_mut() {
+            chunk.dirty = false;
+        }
+    }
+
+    fn dirty(world: &World) -> Vec<[i16; 3]> {
+        let mut dirty = world.chunks
+            .iter()
+            .filter(|(.., chunk)| chunk.dirty)
+            .map(|(coordinates, ..)| (*coordinates).into())
+            .collect::<Vec<_>>();
+
+        dirty.sort();
+        dirty
+    }
+
+    #[test]
+    fn coordinates_round_towards_negative_infinity() {
+        assert_eq!(Chunk::coordinates(Point3::new(0, 15, 16)), Point3::new(0, 0, 1));
+        assert_eq!(Chunk::coordinates(Point3::new(-1, -16, -17)), Point3::new(-1, -1, -2));
+        assert_eq!(Chunk::coordinates(Point3::new(i16::MIN, i16::MAX, 0)), Point3::new(-2048, 2047, 0));
+    }
+
+    #[test]
+    fn only_modified_chunks_are_dirty() {
+        let mut world = World::default();
+        world.add_tile(Block::at(5, 5, 5));
+        world.add_tile(Block::at(40, 5, 5));
+        assert_eq!(dirty(&world), [[0, 0, 0], [2, 0, 0]]);
+
+        clean(&mut world);
+        world.add_tile(Block::at(6, 5, 5));
+        assert_eq!(dirty(&world), [[0, 0, 0]]);
+
+        clean(&mut world);
+        world.get_tile_mut(Point3::new(40, 5, 5)).unwrap().set_color([0.0; 3]);
+        assert_eq!(dirty(&world), [[2, 0, 0]]);
+    }
+
+    #[test]
+    fn neighbors_across_a_border_are_dirty() {
+        let mut world = World::default();
+        world.add_tile(Block::at(16, 8, 8));
+        world.add_tile(Block::at(8, 8, -1));
+        world.add_tile(Block::at(14, 8, 8));
+        clean(&mut world);
+
+        // The face between 15 and 16 is culled, so both sides have to be rebuilt
+        world.add_tile(Block::at(15, 8, 8));
+        assert_eq!(dirty(&world), [[0, 0, 0], [1, 0, 0]]);
+
+        clean(&mut world);
+        world.remove_tile(Point3::new(15, 8, 8));
+        assert_eq!(dirty(&world), [[0, 0, 0], [1, 0, 0]]);
+
+        clean(&mut world);
+        world.add_tile(Block::at(8, 8, 0));
+        assert_eq!(dirty(&world), [[0, 0, -1], [0, 0, 0]]);
+    }
+
+    #[test]
+    fn empty_chunks_are_dropped() {
+        let mut world = World::default();
+        world.add_tile(Block::at(0, 0, 0));
+        world.add_tile(Block::at(-20, 0, 0));
+
+        world.remove_tile(Point3::new(-20, 0, 0));
+        assert_eq!(world.chunks.keys().copied().collect::<Vec<_>>(), [Point3::new(0, 0, 0)]);
+
+        // Removing a tile that doesn't exist creates nothing
+        assert!(world.remove_tile(Point3::new(-20, 0, 0)).is_none());
+        assert_eq!(world.chunks.len(), 1);
+    }
+}
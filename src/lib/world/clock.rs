@@ -0,0 +1,144 @@
+use super::World;
+
+// Simulated time, which only advances while the World isn't paused.
+// Physics, entity lifetimes and timers are all driven by it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Clock {
+    time_scale: f32,
+    paused: bool,
+    // The number of seconds simulated so far, and by the most recent tick
+    elapsed: f64,
+    delta: f32
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            time_scale: 1.0,
+            paused: false,
+            elapsed: 0.0,
+            delta: 0.0
+        }
+    }
+}
+
+impl<'a> World<'a> {
+    pub fn time_scale(&self) -> f32 {
+        self.clock.time_scale
+    }
+
+    // Scales the time simulated by each tick, e.g. 0.5 for slow motion.
+    // Negative scales are treated as 0
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.clock.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clock.paused
+    }
+
+    // Freezes physics, lifetimes and timers. Systems are still run, see World::delta_time
+    pub fn set_paused(&mut self, paused: bool) {
+        self.clock.paused = paused;
+    }
+
+    // The number of seconds simulated since the World was created
+    pub fn elapsed(&self) -> f64 {
+        self.clock.elapsed
+    }
+
+    // The number of seconds simulated by the most recent tick,
+    // which is 0 while the World is paused
+    pub fn delta_time(&self) -> f32 {
+        self.clock.delta
+    }
+
+    // Called once at the start of every tick
+    pub(crate) fn advance_clock(&mut self) {
+        self.clock.delta = match self.clock.paused {
+            true => 0.0,
+            false => self.physics_config.timestep * self.clock.time_scale
+        };
+
+        self.clock.elapsed += self.clock.delta as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cgmath::Vector3;
+
+    use crate::world::{ testing::Ball, PhysicsConfig, World };
+
+    // A timestep that adds up exactly, without gravity
+    fn world<'a>() -> World<'a> {
+        let mut world = World::default();
+        world.set_physics_config(PhysicsConfig { timestep: 0.25, gravity: Vector3::new(0.0, 0.0, 0.0), ..PhysicsConfig::default() } );
+        world
+    }
+
+    fn tick(world: &mut World) {
+        world.advance_clock();
+        world.resolve_entity_lifetimes();
+        world.resolve_entity_physics();
+    }
+
+    #[test]
+    fn time_scale_and_pause() {
+        let mut world = world();
+
+        tick(&mut world);
+        assert_eq!((world.elapsed(), world.delta_time()), (0.25, 0.25));
+
+        world.set_time_scale(2.0);
+        tick(&mut world);
+        assert_eq!((world.elapsed(), world.delta_time()), (0.75, 0.5));
+
+        world.set_paused(true);
+        tick(&mut world);
+        assert_eq!((world.elapsed(), world.delta_time()), (0.75, 0.0));
+
+        world.set_paused(false);
+        world.set_time_scale(-1.0);
+        assert_eq!(world.time_scale(), 0.0);
+        tick(&mut world);
+        assert_eq!((world.elapsed(), world.delta_time()), (0.75, 0.0));
+    }
+
+    #[test]
+    fn lifetimes_follow_simulated_time() {
+        let mut world = world();
+        let ball = world.add_entity(Ball::default(), Some(Duration::from_secs(1)));
+
+        // Half speed, so the first second takes 8 ticks
+        world.set_time_scale(0.5);
+        for _ in 0..7 {
+            tick(&mut world);
+        }
+
+        world.set_paused(true);
+        for _ in 0..10 {
+            tick(&mut world);
+        }
+        assert!(world.is_alive(ball));
+
+        world.set_paused(false);
+        tick(&mut world);
+        assert!(!world.is_alive(ball));
+    }
+
+    #[test]
+    fn physics_follows_simulated_time() {
+        let mut world = world();
+        let ball = world.add_entity(Ball { velocity: [1.0, 0.0, 0.0], ..Ball::default() }, None);
+
+        world.set_time_scale(0.5);
+        tick(&mut world);
+        world.set_paused(true);
+        tick(&mut world);
+
+        assert_eq!(world.entity(ball).unwrap().center().x, 0.125);
+    }
+}
@@ -0,0 +1,41 @@
+use cgmath::{ Point3, Vector3 };
+
+use super::EntityHandle;
+
+// Produced by the physics step, see GameData::drain_contact_events
+#[derive(Clone, Copy, Debug)]
+pub enum ContactEvent {
+    // The entity was stopped by the tile at the given position.
+    // The normal points away from the face of the tile that was hit
+    Tile {
+        entity: EntityHandle,
+        position: Point3<i16>,
+        normal: Vector3<f32>
+    },
+    // The entity ran into the other entity, or their bounding boxes overlap.
+    // Reported once per pair
+    Entity {
+        entity: EntityHandle,
+        other: EntityHandle
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerPhase {
+    // The entity started overlapping the tile during the last tick
+    Enter,
+    // The entity has overlapped the tile for more than one tick
+    Stay,
+    // The entity stopped overlapping the tile, was removed, or the tile is no longer a trigger.
+    // An entity that is despawned by a trigger callback has already been removed when its Exit is dispatched
+    Exit
+}
+
+// Passed to trigger callbacks, see World::add_trigger_callback
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerEvent {
+    pub phase: TriggerPhase,
+    pub entity: EntityHandle,
+    // The position of the trigger tile
+    pub position: Point3<i16>
+}
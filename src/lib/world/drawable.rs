@@ -1,12 +1,69 @@
-use cgmath::Point3;
+use cgmath::{ Point3, Vector3 };
+
+use wgpu::{
+    Buffer,
+    Device,
+    util::DeviceExt
+};
 
 use crate::vertex::Vertex;
 
+#[derive(Clone, Default)]
 pub struct Triangles {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>
 }
 
+impl Triangles {
+    // Moves every vertex by the given offset,
+    // useful when an entity returns a shared mesh from build_object_data
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        for vertex in self.vertices.iter_mut() {
+            vertex.position[0] += offset.x;
+            vertex.position[1] += offset.y;
+            vertex.position[2] += offset.z;
+        }
+    }
+}
+
+// Triangles that have been uploaded to the GPU
+pub(crate) struct GeometryBuffers {
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) index_buffer: Buffer,
+    pub(crate) index_count: u32
+}
+
+impl GeometryBuffers {
+    // Returns None if there is nothing to draw
+    pub(crate) fn new(device: &Device, triangles: &Triangles) -> Option<Self> {
+        if triangles.indices.is_empty() {
+            return None;
+        }
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(triangles.vertices.as_slice()),
+                usage: wgpu::BufferUsages::VERTEX
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(triangles.indices.as_slice()),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Some(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: triangles.indices.len() as u32
+        } )
+    }
+}
+
 pub trait Drawable {
     fn center(&self) -> Point3<f32>;
     fn color(&self) -> [f32; 3];
@@ -15,6 +72,12 @@ pub trait Drawable {
     fn set_center(&mut self, center: Point3<f32>);
     fn set_color(&mut self, color: [f32; 3]);
     fn set_light(&mut self, light: [f32; 4]);
+
+    // Whether the Drawable's light is occluded by the World's geometry
+    fn casts_shadows(&self) -> bool { false }
+
+    // Identifies the implementor when the World is saved (see Registry)
+    fn type_name(&self) -> &'static str { std::any::type_name::<Self>() }
     
     fn build_object_data(&self) -> Triangles;
 }
\ No newline at end of file
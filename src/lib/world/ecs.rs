@@ -0,0 +1,420 @@
+use std::{
+    any::{ Any, TypeId },
+    collections::HashMap,
+    mem
+};
+
+use super::{
+    Entity,
+    EntityHandle,
+    World
+};
+
+// Hands out entity handles. The index of a despawned entity is reused,
+// but with a new generation, so stale handles never refer to the new entity
+#[derive(Clone, Default)]
+pub(crate) struct Allocator {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>
+}
+
+impl Allocator {
+    pub(crate) fn allocate(&mut self) -> EntityHandle {
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                EntityHandle::new(index, self.generations[index as usize])
+            },
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                EntityHandle::new(self.generations.len() as u32 - 1, 0)
+            }
+        }
+    }
+
+    // Returns false if the entity was already dead
+    pub(crate) fn free(&mut self, handle: EntityHandle) -> bool {
+        if !self.is_alive(handle) {
+            return false;
+        }
+
+        let index = handle.index();
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(index as u32);
+
+        true
+    }
+
+    // Frees every entity, without forgetting the generations that were handed out
+    pub(crate) fn free_all(&mut self) {
+        for (index, alive) in self.alive.iter_mut().enumerate() {
+            if *alive {
+                *alive = false;
+                self.generations[index] = self.generations[index].wrapping_add(1);
+                self.free.push(index as u32);
+            }
+        }
+    }
+
+    pub(crate) fn is_alive(&self, handle: EntityHandle) -> bool {
+        let index = handle.index();
+        index < self.alive.len() && self.alive[index] && self.generations[index] == handle.generation()
+    }
+}
+
+// Values of a single type, indexed by entity.
+// Each value is stored with its entity's handle, so stale handles are rejected
+pub(crate) struct Storage<T> {
+    slots: Vec<Option<(EntityHandle, T)>>
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self { slots: Vec::new() }
+    }
+}
+
+impl<T> Storage<T> {
+    // Returns the value that the entity previously had
+    pub(crate) fn insert(&mut self, handle: EntityHandle, value: T) -> Option<T> {
+        let index = handle.index();
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+
+        self.slots[index]
+            .replace((handle, value))
+            .filter(|(previous, ..)| *previous == handle)
+            .map(|(.., previous)| previous)
+    }
+
+    pub(crate) fn remove(&mut self, handle: EntityHandle) -> Option<T> {
+        if !self.contains(handle) {
+            return None;
+        }
+
+        self.slots[handle.index()].take().map(|(.., value)| value)
+    }
+
+    pub(crate) fn contains(&self, handle: EntityHandle) -> bool {
+        matches!(self.slots.get(handle.index()), Some(Some((stored, ..))) if *stored == handle)
+    }
+
+    pub(crate) fn get(&self, handle: EntityHandle) -> Option<&T> {
+        match self.slots.get(handle.index()) {
+            Some(Some((stored, value))) if *stored == handle => Some(value),
+            _ => None
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: EntityHandle) -> Option<&mut T> {
+        match self.slots.get_mut(handle.index()) {
+            Some(Some((stored, value))) if *stored == handle => Some(value),
+            _ => None
+        }
+    }
+
+    // In ascending order of index
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (EntityHandle, &T)> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.as_ref().map(|(handle, value)| (*handle, value)))
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (EntityHandle, &mut T)> {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.as_mut().map(|(handle, value)| (*handle, value)))
+    }
+
+    pub(crate) fn handles(&self) -> impl Iterator<Item = EntityHandle> + '_ {
+        self.iter().map(|(handle, ..)| handle)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
+// Lets storages of different types be kept in the same map
+trait AnyStorage {
+    fn remove_entity(&mut self, handle: EntityHandle);
+    fn contains(&self, handle: EntityHandle) -> bool;
+    fn handles(&self) -> Vec<EntityHandle>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn contains(&self, handle: EntityHandle) -> bool {
+        Storage::contains(self, handle)
+    }
+
+    fn handles(&self) -> Vec<EntityHandle> {
+        Storage::handles(self).collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// The components attached to entities, one Storage per component type
+#[derive(Default)]
+pub(crate) struct Components {
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>
+}
+
+impl Components {
+    pub(crate) fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    pub(crate) fn storage_mut<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+
+    // Only used when a component is inserted, lookups don't create storages
+    pub(crate) fn storage_or_default<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<Storage<T>>::default())
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storages are keyed by the type they store")
+    }
+
+    pub(crate) fn remove_entity(&mut self, handle: EntityHandle) {
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(handle);
+        }
+    }
+
+    // Every entity that has all of the given component types
+    fn query(&self, types: &[TypeId]) -> Vec<EntityHandle> {
+        let storages = types
+            .iter()
+            .map(|type_id| self.storages.get(type_id))
+            .collect::<Option<Vec<_>>>();
+
+        match storages.as_deref() {
+            Some([first, rest @ ..]) => first
+                .handles()
+                .into_iter()
+                .filter(|handle| rest.iter().all(|storage| storage.contains(*handle)))
+                .collect(),
+            _ => Vec::new()
+        }
+    }
+}
+
+// A set of component types that entities can be queried by (see World::query).
+// Implemented for tuples of up to four types
+pub trait ComponentSet {
+    fn type_ids() -> Vec<TypeId>;
+}
+
+macro_rules! component_set {
+    ($($component:ident),+) => {
+        impl<$($component: 'static),+> ComponentSet for ($($component,)+) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$component>()),+]
+            }
+        }
+    };
+}
+
+component_set!(A);
+component_set!(A, B);
+component_set!(A, B, C);
+component_set!(A, B, C, D);
+
+// When a system runs, relative to the physics step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemStage {
+    BeforePhysics,
+    AfterPhysics
+}
+
+pub(crate) type System<'a> = Box<dyn FnMut(&mut World<'a>) + 'a>;
+
+impl<'a> World<'a> {
+    // Creates an entity that only holds components, it isn't drawn or simulated
+    pub fn spawn(&mut self) -> EntityHandle {
+        self.allocator.allocate()
+    }
+
+    // The Entity that is drawn and simulated for the given handle,
+    // None if the handle was created by spawn or the entity has been removed
+    pub fn entity(&self, handle: EntityHandle) -> Option<&(dyn Entity + 'static)> {
+        self.entity_objects.get(handle).map(|entity| entity.as_ref())
+    }
+
+    pub fn entity_mut(&mut self, handle: EntityHandle) -> Option<&mut (dyn Entity + 'static)> {
+        // The entity may be moved
+        self.entity_broadphase.get_mut().take();
+
+        self.entity_objects.get_mut(handle).map(|entity| entity.as_mut())
+    }
+
+    // Every entity that is drawn and simulated
+    pub fn entities(&self) -> impl Iterator<Item = EntityHandle> + '_ {
+        self.entity_objects.handles()
+    }
+
+    // Replaces the entity's existing component of the same type.
+    // Components can't be added to entities that have been removed
+    pub fn insert_component<T: 'static>(&mut self, handle: EntityHandle, component: T) {
+        if self.allocator.is_alive(handle) {
+            self.components.storage_or_default().insert(handle, component);
+        }
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, handle: EntityHandle) -> Option<T> {
+        self.components.storage_mut()?.remove(handle)
+    }
+
+    pub fn component<T: 'static>(&self, handle: EntityHandle) -> Option<&T> {
+        self.components.storage()?.get(handle)
+    }
+
+    pub fn component_mut<T: 'static>(&mut self, handle: EntityHandle) -> Option<&mut T> {
+        self.components.storage_mut()?.get_mut(handle)
+    }
+
+    pub fn has_component<T: 'static>(&self, handle: EntityHandle) -> bool {
+        self.component::<T>(handle).is_some()
+    }
+
+    // Every component of the given type, along with the entity it belongs to
+    pub fn components<T: 'static>(&self) -> impl Iterator<Item = (EntityHandle, &T)> {
+        self.components.storage().into_iter().flat_map(|storage| storage.iter())
+    }
+
+    pub fn components_mut<T: 'static>(&mut self) -> impl Iterator<Item = (EntityHandle, &mut T)> {
+        self.components.storage_mut().into_iter().flat_map(|storage| storage.iter_mut())
+    }
+
+    // Every entity that has all of the components in the set, e.g. `world.query::<(Health, Poisoned)>()`.
+    // The handles are collected, so the World can be modified while iterating over them
+    pub fn query<Q: ComponentSet>(&self) -> Vec<EntityHandle> {
+        self.components.query(&Q::type_ids())
+    }
+
+    // Systems are run every tick in the order they were added, either before or after the physics step
+    pub fn add_system<F>(&mut self, stage: SystemStage, system: F)
+        where F: FnMut(&mut World<'a>) + 'a {
+
+        self.systems.push((stage, Box::new(system)));
+    }
+
+    pub(crate) fn run_systems(&mut self, stage: SystemStage) {
+        // The systems are moved out of the World so that they can be given access to it
+        let mut systems = mem::take(&mut self.systems);
+        for (.., system) in systems.iter_mut().filter(|(s, ..)| *s == stage) {
+            system(self);
+        }
+
+        // Systems may have added more systems
+        systems.append(&mut self.systems);
+        self.systems = systems;
+    }
+
+    // Whether the handle refers to an entity that hasn't been despawned
+    pub fn is_alive(&self, handle: EntityHandle) -> bool {
+        self.allocator.is_alive(handle)
+    }
+
+    // Removes the entity along with its components and tags, returns false if it was already dead.
+    // The despawn callbacks are run first, while the entity can still be accessed.
+    // Entities despawned by a callback are removed once the current despawn completes
+    pub fn despawn(&mut self, handle: EntityHandle) -> bool {
+        if !self.is_alive(handle) || self.despawn_queue.contains(&handle) {
+            return false;
+        }
+
+        self.despawn_queue.push_back(handle);
+        if self.despawn_queue.len() > 1 {
+            return true;
+        }
+
+        while let Some(&handle) = self.despawn_queue.front() {
+            for child in self.children(handle) {
+                self.despawn(child);
+            }
+
+            // The callbacks are moved out of the World so that they can be given access to it
+            let mut callbacks = mem::take(&mut self.despawn_callbacks);
+            for callback in callbacks.iter_mut() {
+                callback(self, handle);
+            }
+
+            // Callbacks may have added more callbacks
+            callbacks.append(&mut self.despawn_callbacks);
+            self.despawn_callbacks = callbacks;
+
+            // Trigger tiles that the entity occupied see it leave. The entity can still be accessed,
+            // unless the Exit events are queued behind the trigger callback that despawned it
+            self.exit_triggers(handle);
+
+            self.remove_entity(handle);
+            self.despawn_queue.pop_front();
+        }
+
+        true
+    }
+
+    // Callbacks are run whenever an entity is despawned, including when its lifetime expires
+    pub fn add_despawn_callback<F>(&mut self, callback: F)
+        where F: FnMut(&mut World<'a>, EntityHandle) + 'a {
+
+        self.despawn_callbacks.push(Box::new(callback));
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.allocator.free(handle);
+
+        self.entity_objects.remove(handle);
+        self.entity_broadphase.get_mut().take();
+        self.entity_lifetimes.remove(handle);
+        self.components.remove_entity(handle);
+        self.lights.remove(handle);
+        self.attachments.remove(handle);
+        self.entity_tags.retain(|_, tagged| *tagged != handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::World;
+
+    #[test]
+    fn lookups_do_not_create_storages() {
+        let mut world = World::default();
+        let handle = world.spawn();
+
+        assert_eq!(world.component_mut::<u32>(handle), None);
+        assert_eq!(world.remove_component::<u32>(handle), None);
+        assert_eq!(world.components_mut::<u32>().count(), 0);
+        assert!(world.components.storages.is_empty());
+
+        world.insert_component(handle, 7u32);
+        assert_eq!(world.remove_component::<u32>(handle), Some(7));
+        assert_eq!(world.components.storages.len(), 1);
+    }
+}
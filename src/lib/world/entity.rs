@@ -1,36 +1,69 @@
-use std::{
-    cell::{ RefCell, Ref, RefMut }, 
-    rc::Rc, 
-    ops::Deref
-};
+use cgmath::{ One, Quaternion, Vector3 };
 
-use cgmath::Vector3;
+use crate::import;
 
-use super::drawable;
+use super::{ drawable, Aabb, PhysicalProperties, World };
 
 pub trait Entity: drawable::Drawable {
     fn velocity(&self) -> Vector3<f32>;
     fn collisions(&self) -> Vector3<bool>;
+    // Only used by PhysicsModel::Legacy, see physical_properties
     fn weight(&self) -> f32;
 
     fn set_velocity(&mut self, velocity: Vector3<f32>);
     fn set_collisions(&mut self, collisions: Vector3<bool>);
     fn set_weight(&mut self, weight: f32);
+
+    // Half of the size of the entity's bounding box along each axis, which is centered on the entity
+    fn half_extents(&self) -> Vector3<f32> { Vector3::new(0.25, 0.25, 0.25) }
+
+    // The space the entity occupies, which collides with the World's tiles
+    fn bounds(&self) -> Aabb { Aabb::from_center(self.center(), self.half_extents()) }
+
+    // Mass, drag, friction and restitution (used by PhysicsModel::Standard)
+    fn physical_properties(&self) -> PhysicalProperties { PhysicalProperties::default() }
+
+    // A bit set of the layers the entity belongs to.
+    // Two entities block each other when each one's mask contains one of the other's layers.
+    // Overlapping entities are reported regardless of their layers (see ContactEvent::Entity)
+    fn collision_layers(&self) -> u32 { 1 }
+    fn collision_mask(&self) -> u32 { u32::MAX }
+
+    // Entities that return a Mesh are drawn with instancing, transformed by their
+    // center, rotation and scale. Their build_object_data is never called.
+    // The Mesh should be centered on the origin
+    fn mesh(&self) -> Option<import::Mesh> { None }
+
+    // Only applies to entities with a Mesh
+    fn rotation(&self) -> Quaternion<f32> { Quaternion::one() }
+
+    // Only applies to entities with a Mesh, negative scales are not supported
+    fn scale(&self) -> Vector3<f32> { Vector3::new(1.0, 1.0, 1.0) }
 }
 
-#[derive(Clone)]
-pub struct EntityHandle(Rc<RefCell<dyn Entity>>);
+// Identifies an entity within its World, see World::entity and World::component.
+// Handles are generational, so a handle to a removed entity never refers to another entity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityHandle {
+    index: u32,
+    generation: u32
+}
 
 impl EntityHandle {
-    pub fn new(entity: impl Entity + 'static) -> Self {
-        Self(Rc::new(RefCell::new(entity)))
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index as usize
     }
 
-    pub fn borrow(&self) -> Ref<'_, dyn Entity> {
-        self.0.deref().borrow()
+    pub(crate) fn generation(&self) -> u32 {
+        self.generation
     }
 
-    pub fn borrow_mut(&mut self) -> RefMut<'_, dyn Entity> {
-        self.0.deref().borrow_mut()
+    // Whether the entity is still in the World, handles to despawned entities can be kept safely
+    pub fn is_alive(&self, world: &World) -> bool {
+        world.is_alive(*self)
     }
 }
\ No newline at end of file
@@ -0,0 +1,214 @@
+use cgmath::Point3;
+
+use crate::vertex::Vertex;
+
+use super::Triangles;
+
+// Builds the mesh of all opaque cubes within the region [min, min + size).
+// Faces shared by two opaque cubes are culled and coplanar faces of the same color are merged.
+// `voxel` returns the color of the opaque cube at a position, it is also queried
+// one tile outside of the region so that faces on the border are culled correctly
+pub(crate) fn greedy_mesh<F>(
+    min: Point3<i16>,
+    size: [i16; 3],
+    voxel: F,
+    triangles: &mut Triangles
+) where F: Fn(Point3<i16>) -> Option<[f32; 3]> {
+    let min = [min.x, min.y, min.z];
+
+    // Sample the region (plus a 1 tile border) once, lookups are potentially expensive
+    let padded = [size[0] + 2, size[1] + 2, size[2] + 2];
+    let padded_index = |p: [i16; 3]| {
+        (p[0] + 1) as usize
+            + (p[1] + 1) as usize * padded[0] as usize
+            + (p[2] + 1) as usize * padded[0] as usize * padded[1] as usize
+    };
+
+    let mut grid = vec![None; padded.iter().map(|&l| l as usize).product()];
+    for z in -1..(size[2] + 1) {
+        for y in -1..(size[1] + 1) {
+            for x in -1..(size[0] + 1) {
+                let offset = [x, y, z];
+
+                // The border can lie beyond the edge of the World, where there are no cubes
+                let position = [0, 1, 2].map(|axis| i16::try_from(min[axis] as i32 + offset[axis] as i32).ok());
+                if let [Some(x), Some(y), Some(z)] = position {
+                    grid[padded_index(offset)] = voxel(Point3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    let sample = |p: [i16; 3]| grid[padded_index(p)];
+
+    for d in 0..3 {
+        // The face is spanned by (u, v), where u x v points along d
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+
+        for sign in [1i16, -1] {
+            let mut mask = vec![None; size[u] as usize * size[v] as usize];
+
+            for slice in 0..size[d] {
+                // Determine which faces of this slice are visible
+                for j in 0..size[v] {
+                    for i in 0..size[u] {
+                        let mut position = [0; 3];
+                        position[d] = slice;
+                        position[u] = i;
+                        position[v] = j;
+
+                        let mut neighbor = position;
+                        neighbor[d] += sign;
+
+                        mask[(i + j * size[u]) as usize] = match sample(neighbor) {
+                            Some(..) => None,
+                            None => sample(position)
+                        };
+                    }
+                }
+
+                // Merge visible faces of the same color into rectangles
+                for j in 0..size[v] {
+                    let mut i = 0;
+                    while i < size[u] {
+                        let color = match mask[(i + j * size[u]) as usize] {
+                            Some(color) => color,
+                            None => { i += 1; continue; }
+                        };
+
+                        let mut width = 1;
+                        while i + width < size[u]
+                            && mask[(i + width + j * size[u]) as usize] == Some(color) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        'grow: while j + height < size[v] {
+                            for k in i..(i + width) {
+                                if mask[(k + (j + height) * size[u]) as usize] != Some(color) {
+                                    break 'grow;
+                                }
+                            }
+
+                            height += 1;
+                        }
+
+                        for l in j..(j + height) {
+                            for k in i..(i + width) {
+                                mask[(k + l * size[u]) as usize] = None;
+                            }
+                        }
+
+                        let mut corner = [0.0; 3];
+                        corner[d] = (min[d] + slice) as f32 + 0.5 * sign as f32;
+                        corner[u] = (min[u] + i) as f32 - 0.5;
+                        corner[v] = (min[v] + j) as f32 - 0.5;
+
+                        let mut du = [0.0; 3];
+                        du[u] = width as f32;
+
+                        let mut dv = [0.0; 3];
+                        dv[v] = height as f32;
+
+                        let mut normal = [0.0; 3];
+                        normal[d] = sign as f32;
+
+                        push_quad(triangles, corner, du, dv, normal, color, sign > 0);
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn push_quad(
+    triangles: &mut Triangles,
+    corner: [f32; 3],
+    du: [f32; 3],
+    dv: [f32; 3],
+    normal: [f32; 3],
+    color: [f32; 3],
+    front: bool
+) {
+    let offset = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+
+    let positions = [
+        corner,
+        offset(corner, du),
+        offset(offset(corner, du), dv),
+        offset(corner, dv)
+    ];
+
+    let base = triangles.vertices.len() as u32;
+    triangles.vertices.extend(positions.iter().map(|&position| {
+        Vertex { position, color, normal }
+    } ));
+
+    // Counter-clockwise when viewed from the side the normal points towards
+    let indices = if front {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [0, 2, 1, 0, 3, 2]
+    };
+
+    triangles.indices.extend(indices.iter().map(|i| base + i));
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use crate::world::Triangles;
+
+    use super::greedy_mesh;
+
+    // Meshes the given cubes within a 4x4x4 region, returning the number of quads
+    fn quads(cubes: &[[i16; 3]]) -> usize {
+        let mut triangles = Triangles::default();
+        greedy_mesh(
+            Point3::new(0, 0, 0),
+            [4; 3],
+            |position| cubes.contains(&position.into()).then_some([1.0; 3]),
+            &mut triangles
+        );
+
+        assert_eq!(triangles.vertices.len() * 6, triangles.indices.len() * 4);
+        triangles.indices.len() / 6
+    }
+
+    #[test]
+    fn solid_block_is_one_quad_per_side() {
+        let mut cubes = Vec::new();
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    cubes.push([x, y, z]);
+                }
+            }
+        }
+
+        assert_eq!(quads(&cubes), 6);
+    }
+
+    #[test]
+    fn tiles_at_the_edge_of_the_world() {
+        for corner in [i16::MIN, i16::MAX] {
+            let position = Point3::new(corner, corner, corner);
+            let min = position.map(|c| c.div_euclid(16) * 16);
+
+            let mut triangles = Triangles::default();
+            greedy_mesh(min, [16; 3], |p| (p == position).then_some([1.0; 3]), &mut triangles);
+
+            assert_eq!(triangles.indices.len(), 6 * 6);
+        }
+    }
+
+    #[test]
+    fn l_shape() {
+        // The sides facing -x and -y are merged into one quad each.
+        // The inner corner splits the sides facing +x, +y, +z and -z into two quads each
+        assert_eq!(quads(&[[0, 0, 0], [1, 0, 0], [0, 1, 0]]), 10);
+    }
+}
@@ -1,74 +1,186 @@
 pub(crate) mod drawable;
 pub use drawable::{ Drawable, Triangles };
+pub(crate) use drawable::GeometryBuffers;
 
 pub(crate) mod tile;
-pub use tile::Tile;
+pub use tile::{ Tile, TileMut, TileCollision };
 
 pub(crate) mod entity;
 pub use entity::{ Entity, EntityHandle };
 
+pub(crate) mod ecs;
+pub use ecs::{ ComponentSet, SystemStage };
+
+pub(crate) mod clock;
+
+pub(crate) mod scene;
+pub use scene::Attachment;
+
+pub(crate) mod aabb;
+pub use aabb::Aabb;
+
+pub(crate) mod contact;
+pub use contact::{ ContactEvent, TriggerEvent, TriggerPhase };
+
+pub(crate) mod broadphase;
+
+pub(crate) mod physics;
+pub use physics::{ PhysicsConfig, PhysicsModel, PhysicalProperties };
+
+pub(crate) mod raycast;
+pub use raycast::{ TileHit, EntityHit };
+
+pub(crate) mod chunk;
+pub(crate) mod mesher;
+
+pub(crate) mod save;
+pub use save::Registry;
+
+#[cfg(test)]
+pub(crate) mod testing;
+
 use crate::{
-    vertex::Vertex, 
-    light
+    light,
+    import,
+    instance::Instance
 };
 
-use std::{collections::HashMap, time, cmp};
+use std::{borrow::Cow, cell::RefCell, collections::{ BTreeSet, HashMap, VecDeque }, time, ops::Range};
 
-use cgmath::{ 
-    Point3, 
-    Vector3, 
-    Zero 
-};
+use cgmath::Point3;
 
-use wgpu::{
-    Buffer, 
-    Device, 
-    util::DeviceExt
-};
+use wgpu::Device;
+
+// The GPU geometry of a Mesh, along with the range of instances that use it this frame
+pub(crate) struct MeshBuffers {
+    // Keeps the Mesh alive, so that its key can't be reused by another Mesh
+    _mesh: import::Mesh,
+    buffers: Option<GeometryBuffers>,
+    instances: Range<u32>
+}
+
+type TriggerCallback<'a> = Box<dyn FnMut(&mut World<'a>, &TriggerEvent) + 'a>;
+type DespawnCallback<'a> = Box<dyn FnMut(&mut World<'a>, EntityHandle) + 'a>;
 
 #[derive(Default)]
 pub struct World<'a> {
-    tile_objects: HashMap<Point3<i16>, Box<dyn Tile>>,
-    tile_vertices: Vec<Vertex>,
-    tile_indices: Vec<u32>,
-    entity_objects: Vec<EntityHandle>,
-    entity_tags: HashMap<&'a str, EntityHandle>,
-    entity_lifetimes: Vec<(time::Instant, time::Duration)>
+    chunks: HashMap<Point3<i16>, chunk::Chunk>,
+    allocator: ecs::Allocator,
+    entity_objects: ecs::Storage<Box<dyn Entity>>,
+    entity_tags: HashMap<Cow<'a, str>, EntityHandle>,
+    // The simulated time at which each entity is despawned (if it doesn't live forever)
+    entity_lifetimes: ecs::Storage<f64>,
+    components: ecs::Components,
+    // Lights that don't belong to a tile or entity, see add_light
+    lights: ecs::Storage<(Point3<f32>, [f32; 4])>,
+    attachments: ecs::Storage<Attachment>,
+    systems: Vec<(SystemStage, ecs::System<'a>)>,
+    despawn_callbacks: Vec<DespawnCallback<'a>>,
+    // Entities that are being despawned, the first is the one whose callbacks are running
+    despawn_queue: VecDeque<EntityHandle>,
+    mesh_buffers: HashMap<*const Triangles, MeshBuffers>,
+    // The center of each entity before the most recent tick
+    previous_centers: HashMap<EntityHandle, Point3<f32>>,
+    contact_events: Vec<ContactEvent>,
+    // None once an entity has been added, removed or modified since it was built
+    entity_broadphase: RefCell<Option<broadphase::EntityBroadphase>>,
+    physics_config: PhysicsConfig,
+    clock: clock::Clock,
+    trigger_callbacks: Vec<TriggerCallback<'a>>,
+    // The trigger tiles overlapped by each entity during the previous tick
+    trigger_occupants: BTreeSet<(EntityHandle, [i16; 3])>,
+    // Events that are waiting for the trigger callbacks, see World::run_trigger_callbacks
+    trigger_queue: VecDeque<TriggerEvent>,
+    dispatching_triggers: bool
 }
 
 impl<'a> World<'a> {
+    // Adding a tile to an occupied position replaces the existing tile
     pub fn add_tile(&mut self, tile: impl Tile + 'static) {
-        let mut triangles = tile.build_object_data();
+        self.insert_tile(Box::new(tile));
+    }
 
-        self.tile_objects.insert(tile.position(), Box::new(tile));
+    // Same as add_tile, but returns the tile that previously occupied the position
+    pub fn replace_tile(&mut self, tile: impl Tile + 'static) -> Option<Box<dyn Tile>> {
+        self.insert_tile(Box::new(tile))
+    }
 
-        let mut offset_indices = triangles.indices
-            .iter()
-            .map(|i| *i + self.tile_vertices.len() as u32)
-            .collect::<Vec<u32>>();
+    pub fn remove_tile(&mut self, position: Point3<i16>) -> Option<Box<dyn Tile>> {
+        let coordinates = chunk::Chunk::coordinates(position);
 
-        self.tile_indices.append(&mut offset_indices);
-        self.tile_vertices.append(&mut triangles.vertices);
+        let chunk = self.chunks.get_mut(&coordinates)?;
+        let tile = chunk.tiles.remove(&position)?;
+        
+        // Empty chunks are dropped along with their GPU buffers
+        if chunk.tiles.is_empty() {
+            self.chunks.remove(&coordinates);
+        }
+
+        self.mark_dirty(position);
+
+        Some(tile)
     }
 
+    fn insert_tile(&mut self, tile: Box<dyn Tile>) -> Option<Box<dyn Tile>> {
+        let position = tile.position();
+
+        let replaced = self.chunks
+            .entry(chunk::Chunk::coordinates(position))
+            .or_default()
+            .tiles
+            .insert(position, tile);
+
+        self.mark_dirty(position);
+
+        replaced
+    }
+
+    // Marks the chunk containing the position as modified, along with any neighboring
+    // chunks, since hidden faces are culled across chunk borders
+    fn mark_dirty(&mut self, position: Point3<i16>) {
+        let neighbors = [
+            (0, 0, 0),
+            (1, 0, 0), (-1, 0, 0),
+            (0, 1, 0), (0, -1, 0),
+            (0, 0, 1), (0, 0, -1)
+        ];
+
+        for (dx, dy, dz) in neighbors {
+            let neighbor = Point3::new(
+                position.x.saturating_add(dx),
+                position.y.saturating_add(dy),
+                position.z.saturating_add(dz)
+            );
+
+            if let Some(chunk) = self.chunks.get_mut(&chunk::Chunk::coordinates(neighbor)) {
+                chunk.dirty = true;
+            }
+        }
+    }
+
+    // The lifetime is measured in simulated time, so it's affected by the time scale and pausing
     pub fn add_entity(
         &mut self, 
         entity: impl Entity + 'static,
         lifetime: Option<time::Duration>
     ) -> EntityHandle {
-        let handle = EntityHandle::new(entity);
-        let handle_clone = handle.clone();
-        self.entity_objects.push(handle);
+        self.insert_entity(Box::new(entity), lifetime)
+    }
+
+    fn insert_entity(
+        &mut self,
+        entity: Box<dyn Entity>,
+        lifetime: Option<time::Duration>
+    ) -> EntityHandle {
+        let handle = self.allocator.allocate();
+        self.entity_objects.insert(handle, entity);
+        self.entity_broadphase.get_mut().take();
         
-        self.entity_lifetimes.push((
-            time::Instant::now(), 
-            match lifetime { 
-                Some(lifetime) => lifetime, 
-                None => time::Duration::MAX 
-            } 
-        ));
-
-        handle_clone
+        if let Some(lifetime) = lifetime {
+            self.entity_lifetimes.insert(handle, self.elapsed() + lifetime.as_secs_f64());
+        }
+
+        handle
     }
 
     pub fn add_entity_with_tag(
@@ -78,14 +190,38 @@ impl<'a> World<'a> {
         lifetime: Option<time::Duration>
     ) -> EntityHandle {
         let handle = self.add_entity(entity, lifetime);
-        let handle_clone = handle.clone();
-        self.entity_tags.insert(tag, handle);
+        self.tag_entity(tag, handle);
+
+        handle
+    }
+
+    // Assigns the tag to the entity, taking it from any entity that previously had it.
+    // An entity can have several tags, all of which are removed when it is despawned
+    pub fn tag_entity(&mut self, tag: &'a str, handle: EntityHandle) -> bool {
+        if !self.is_alive(handle) {
+            return false;
+        }
 
-        handle_clone
+        self.entity_tags.insert(Cow::Borrowed(tag), handle);
+
+        true
+    }
+
+    // The entity that had the tag is returned; it is not despawned
+    pub fn remove_tag(&mut self, tag: &str) -> Option<EntityHandle> {
+        self.entity_tags.remove(tag)
+    }
+
+    // Every tag assigned to the entity
+    pub fn tags(&self, handle: EntityHandle) -> impl Iterator<Item = &str> {
+        self.entity_tags
+            .iter()
+            .filter(move |(.., tagged)| **tagged == handle)
+            .map(|(tag, ..)| tag.as_ref())
     }
 
     pub fn contains_tile(&self, position: &Point3<i16>) -> bool {
-        self.tile_objects.contains_key(position)
+        self.get_tile(*position).is_some()
     }
 
     pub fn contains_entity(&self, tag: &str) -> bool {
@@ -93,145 +229,210 @@ impl<'a> World<'a> {
     }
 
     pub fn get_tile(&self, position: Point3<i16>) -> Option<&(dyn Tile + 'static)> {
-        self.tile_objects
+        self.chunks
+            .get(&chunk::Chunk::coordinates(position))?
+            .tiles
             .get(&position)
             .map(|t| t.as_ref())
     }
 
+    // The tile's geometry is rebuilt after the returned guard is dropped.
+    // If the tile's position was changed, it is moved to its new position,
+    // unless another tile is already there (see TileMut)
+    pub fn get_tile_mut(&mut self, position: Point3<i16>) -> Option<TileMut<'_, 'a>> {
+        if self.contains_tile(&position) {
+            self.mark_dirty(position);
+
+            Some(TileMut { world: self, position })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn tiles(&self) -> impl Iterator<Item = &(dyn Tile + 'static)> {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.tiles.values())
+            .map(|t| t.as_ref())
+    }
+
     pub fn get_entity(&self, tag: &str) -> Option<EntityHandle> {
-        self.entity_tags.get(tag).cloned()
+        self.entity_tags.get(tag).copied()
     }
 
     pub(crate) fn resolve_entity_lifetimes(&mut self) {
-        for index in (0..self.entity_lifetimes.len()).rev() {
-            let (creation_instant, lifetime) = self.entity_lifetimes[index];
-            if matches!(creation_instant.elapsed().cmp(&lifetime), cmp::Ordering::Greater | cmp::Ordering::Equal) {
-                self.entity_objects.remove(index);
-                self.entity_lifetimes.remove(index);
-            }
+        let expired = self.entity_lifetimes
+            .iter()
+            .filter(|(.., expiry)| self.elapsed() >= **expiry)
+            .map(|(handle, ..)| handle)
+            .collect::<Vec<_>>();
+
+        for handle in expired {
+            self.despawn(handle);
         }
     }
 
-    pub(crate) fn resolve_entity_physics(&mut self) {
-        for index in 0..self.entity_objects.len() {
-            let (velocity, weight) = {
-                let entity = self.entity_objects[index].borrow(); // TODO
-                
-                (entity.velocity(), entity.weight())
-            };
-
-            self.apply_displacement_to_entity(index, velocity);
+    // Remembers where each entity and light was before the tick, see interpolate_center
+    pub(crate) fn store_entity_centers(&mut self) {
+        self.previous_centers = self.entity_objects
+            .iter()
+            .map(|(handle, entity)| (handle, entity.center()))
+            .chain(self.lights.iter().map(|(handle, (center, ..))| (handle, *center)))
+            .collect();
+    }
 
-            let gravity = Vector3::new(0.0, -1.0 * weight, 0.0);
-            self.apply_displacement_to_entity(index, gravity);
+    // Where the entity should be drawn, `alpha` of the way through the most recent tick.
+    // Entities that were added during the tick are drawn where they are
+    fn interpolate_center(&self, handle: EntityHandle, center: Point3<f32>, alpha: f32) -> Point3<f32> {
+        match self.previous_centers.get(&handle) {
+            Some(previous) => previous + (center - previous) * alpha,
+            None => center
         }
     }
 
-    fn apply_displacement_to_entity(
-        &mut self, 
-        entity_index: usize,
-        displacement: Vector3<f32>
-    ) {
-        let mut handler = self.entity_objects[entity_index].clone();
-        
-        let (center, weight, velocity) = {
-            let entity = handler.borrow();
-            (entity.center(), entity.weight(), entity.velocity())
-        };
-
-        // collision detection fails when the entity travels more than 1 tile in a single tick
-        let mut actual_displacement = displacement;
-        [actual_displacement.x, actual_displacement.y, actual_displacement.z]
-            .iter_mut()
-            .for_each(|c| *c = c.clamp(-1.0, 1.0));
-
-        let increment = actual_displacement * 0.1;
-
-        // Find the discrete coordinates of the tile containing the entity's new position (velocity + position)
-        fn get_discrete_point(pt: Point3<f32>) -> Point3<i16> {
-            (pt.x.round() as i16, pt.y.round() as i16, pt.z.round() as i16).into()
+    pub(crate) fn build_light_sources(&self, alpha: f32) -> light::LightSources {
+        let mut light_sources = light::LightSources::default();
+
+        for tile in self.tiles() {
+            if let Some(light) = tile.light() {
+                if tile.casts_shadows() {
+                    light_sources.shadow_casters.push(light_sources.lights.len());
+                }
+
+                light_sources.lights.push(light::Light::new(
+                    tile.position().cast::<f32>().unwrap(), 
+                    light
+                ));
+            }
         }
 
-        let mut initial_collisions: Option<Vector3<bool>> = None;
-        let mut collisions: Vector3<bool> = (false, false, false).into();
-        while self.contains_tile(&get_discrete_point(center + actual_displacement)) && !actual_displacement.is_zero() {
-            collisions.x = self.contains_tile(&get_discrete_point(
-                center + Vector3::new(actual_displacement.x, 0.0, 0.0)));
+        for (handle, entity) in self.entity_objects.iter() {
+            if let Some(light) = entity.light() {
+                if entity.casts_shadows() {
+                    light_sources.shadow_casters.push(light_sources.lights.len());
+                }
+
+                light_sources.lights.push(light::Light::new(
+                    self.interpolate_center(handle, entity.center(), alpha), 
+                    light
+                ));
+            }
+        }
 
-            collisions.y = self.contains_tile(&get_discrete_point(
-                center + Vector3::new(0.0, actual_displacement.y, 0.0)));
+        for (handle, (center, light)) in self.lights.iter() {
+            light_sources.lights.push(light::Light::new(
+                self.interpolate_center(handle, *center, alpha),
+                *light
+            ));
+        }
 
-            collisions.z = self.contains_tile(&get_discrete_point(
-                center + Vector3::new(0.0, 0.0, actual_displacement.z)));
-            
-            if initial_collisions.is_none() { initial_collisions = Some(collisions); }
+        light_sources
+    }
 
-            actual_displacement -= increment;
-        }
+    // Rebuilds the meshes of all chunks that were modified since the last call
+    pub(crate) fn build_chunk_buffers(&mut self, device: &Device) {
+        let dirty_chunks = self.chunks
+            .iter()
+            .filter(|(.., chunk)| chunk.dirty)
+            .map(|(coordinates, ..)| *coordinates)
+            .collect::<Vec<_>>();
 
-        {
-            let mut entity = handler.borrow_mut();
-            
-            let mut diff = Vector3::new(0.0, 0.0, 0.0);
-            if let Some(collisions) = initial_collisions {
-                if collisions.x { diff.x = displacement.x; }
-                if collisions.y { diff.y = displacement.y; }
-                if collisions.z { diff.z = displacement.z; }
+        for coordinates in dirty_chunks {
+            let triangles = self.build_chunk_object_data(coordinates);
+
+            if let Some(chunk) = self.chunks.get_mut(&coordinates) {
+                chunk.upload(device, triangles);
             }
-            
-            entity.set_center(center + actual_displacement);
-            entity.set_velocity((velocity - diff) * (1.0 - weight));
-            
         }
     }
 
-    pub(crate) fn build_light_sources(&self) -> (light::LightSources, u32) {
-        let mut light_sources = light::LightSources { 
-            light_uniforms: [
-                light::Light::default(); 
-                light::MAX_LIGHT_SOURCES
-            ]
-        };
+    fn build_chunk_object_data(&self, coordinates: Point3<i16>) -> Triangles {
+        let mut triangles = self.chunks[&coordinates].build_object_data();
 
-        let mut light_count = 0;
-        for (.., tile) in self.tile_objects.iter() {
-            if let Some(light) = tile.light() {
-                light_sources.light_uniforms[light_count].color = light;
-                light_sources.light_uniforms[light_count].position = [
-                    tile.position().x as f32,
-                    tile.position().y as f32,
-                    tile.position().z as f32,
-                    1.0
-                ];
-
-                light_count += 1;
+        mesher::greedy_mesh(
+            coordinates * chunk::CHUNK_SIZE,
+            [chunk::CHUNK_SIZE; 3],
+            |position| self.get_tile(position)
+                .filter(|tile| tile.is_opaque_cube())
+                .map(|tile| tile.color()),
+            &mut triangles
+        );
+
+        triangles
+    }
+
+    pub(crate) fn chunk_buffers(&self) -> impl Iterator<Item = &GeometryBuffers> + Clone {
+        self.chunks
+            .values()
+            .filter_map(|chunk| chunk.buffers.as_ref())
+    }
+
+    // Collects the transform of every entity with a Mesh, grouped by Mesh.
+    // Each Mesh is only uploaded the first time it is used, and dropped once no entity uses it.
+    // The first instance is always the identity, which is shared by all other geometry
+    pub(crate) fn build_entity_instances(&mut self, device: &Device, alpha: f32) -> Vec<Instance> {
+        let mut groups: HashMap<*const Triangles, (import::Mesh, Vec<Instance>)> = HashMap::new();
+
+        for (handle, entity) in self.entity_objects.iter() {
+            if let Some(mesh) = entity.mesh() {
+                let instance = Instance::new(
+                    self.interpolate_center(handle, entity.center(), alpha), 
+                    self.rotation(handle), 
+                    entity.scale()
+                );
+
+                groups
+                    .entry(mesh.key())
+                    .or_insert_with(|| (mesh, Vec::new()))
+                    .1
+                    .push(instance);
             }
         }
 
-        for entity in self.entity_objects.iter().map(|e| e.borrow()) { // TODO
-            if let Some(light) = entity.light() {
-                light_sources.light_uniforms[light_count].color = light;
-                light_sources.light_uniforms[light_count].position = [
-                    entity.center().x,
-                    entity.center().y,
-                    entity.center().z,
-                    1.0
-                ];
-
-                light_count += 1;
-            }
+        self.mesh_buffers.retain(|key, _| groups.contains_key(key));
+
+        let mut instances = vec![Instance::IDENTITY];
+        for (key, (mesh, mesh_instances)) in groups {
+            let start = instances.len() as u32;
+            instances.extend(mesh_instances);
+
+            let range = start..instances.len() as u32;
+            self.mesh_buffers
+                .entry(key)
+                .and_modify(|mesh_buffers| mesh_buffers.instances = range.clone())
+                .or_insert_with(|| MeshBuffers {
+                    buffers: GeometryBuffers::new(device, mesh.triangles()),
+                    _mesh: mesh,
+                    instances: range
+                } );
         }
 
-        (light_sources, light_count as u32)
+        instances
+    }
 
+    // The geometry of each Mesh in use, along with its range of instances
+    pub(crate) fn mesh_buffers(&self) -> impl Iterator<Item = (&GeometryBuffers, Range<u32>)> + Clone {
+        self.mesh_buffers
+            .values()
+            .filter_map(|mesh_buffers| mesh_buffers.buffers
+                .as_ref()
+                .map(|buffers| (buffers, mesh_buffers.instances.clone())))
     }
 
-    pub(crate) fn build_geometry_buffers(&self, device: &mut Device) -> (Buffer, Buffer, u32) {
-        let mut indices = self.tile_indices.clone();
-        let mut vertices = self.tile_vertices.clone();
+    // Entity geometry changes every tick, so it is rebuilt in its entirety.
+    // Entities with a Mesh are excluded (see build_entity_instances)
+    pub(crate) fn build_entity_buffers(&self, device: &Device, alpha: f32) -> Option<GeometryBuffers> {
+        let mut indices = Vec::new();
+        let mut vertices = Vec::new();
+
+        for (handle, entity) in self.entity_objects.iter() {
+            if entity.mesh().is_some() {
+                continue;
+            }
 
-        for entity in self.entity_objects.iter().map(|e| e.borrow()) { // TODO
             let mut triangles = entity.build_object_data();
+            triangles.translate(self.interpolate_center(handle, entity.center(), alpha) - entity.center());
+
             let mut offset_indices = triangles.indices
                 .iter()
                 .map(|i| *i + vertices.len() as u32)
@@ -240,22 +441,30 @@ impl<'a> World<'a> {
             vertices.append(&mut triangles.vertices);
         }
 
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(vertices.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX
-            }
-        );
+        GeometryBuffers::new(device, &Triangles { vertices, indices })
+    }
+}
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(indices.as_slice()),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::{ testing::Ball, World };
+
+    #[test]
+    fn centers_are_interpolated_across_the_last_tick() {
+        let mut world = World::default();
+        let moved = world.add_entity(Ball::at(0.0, 0.0, 0.0), None);
+
+        world.store_entity_centers();
+        world.entity_mut(moved).unwrap().set_center(Point3::new(4.0, 2.0, 0.0));
+        let added = world.add_entity(Ball::at(1.0, 1.0, 1.0), None);
+
+        let center = Point3::new(4.0, 2.0, 0.0);
+        assert_eq!(world.interpolate_center(moved, center, 0.0), Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(world.interpolate_center(moved, center, 0.25), Point3::new(1.0, 0.5, 0.0));
+        assert_eq!(world.interpolate_center(moved, center, 1.0), center);
 
-        (vertex_buffer, index_buffer, indices.len() as u32)
+        assert_eq!(world.interpolate_center(added, Point3::new(1.0, 1.0, 1.0), 0.5), Point3::new(1.0, 1.0, 1.0));
     }
 }
@@ -0,0 +1,977 @@
+use std::{
+    collections::BTreeSet,
+    mem
+};
+
+use cgmath::{
+    InnerSpace,
+    MetricSpace,
+    Point3,
+    Vector3,
+    Zero
+};
+
+use super::{
+    broadphase::{ EntityBroadphase, SpatialHash },
+    Aabb,
+    ContactEvent,
+    EntityHandle,
+    TileCollision,
+    TriggerEvent,
+    TriggerPhase,
+    World
+};
+
+// The largest distance an entity moves along each axis before collisions are resolved
+const SWEEP_STEP: f32 = 0.5;
+
+// Boxes closer than this are considered to be touching
+const SWEEP_EPSILON: f32 = 1e-4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhysicsModel {
+    // Velocities are measured in tiles per second, and entities are
+    // accelerated by gravity. See Entity::physical_properties
+    Standard,
+    // The original model, where velocities are measured in tiles per tick.
+    // Entity::weight scales gravity and damps velocity,
+    // the timestep, terminal velocity and physical properties are ignored.
+    // The time scale (see World::set_time_scale) scales the distance moved each tick
+    Legacy
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsConfig {
+    pub model: PhysicsModel,
+    // Tiles per second squared (Standard), or tiles per tick scaled by weight (Legacy)
+    pub gravity: Vector3<f32>,
+    // The number of seconds simulated by each tick, before the World's time scale is applied
+    pub timestep: f32,
+    // The greatest speed an entity can reach, in tiles per second
+    pub terminal_velocity: f32
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            model: PhysicsModel::Standard,
+            gravity: Vector3::new(0.0, -20.0, 0.0),
+            timestep: 1.0 / 60.0,
+            terminal_velocity: 50.0
+        }
+    }
+}
+
+impl PhysicsConfig {
+    // Moves entities by the same amounts per tick as the engine did before PhysicsConfig was introduced.
+    // Collisions are still swept like in the Standard model, so fast entities stop at the first obstacle
+    // instead of passing through it
+    pub fn legacy() -> Self {
+        Self {
+            model: PhysicsModel::Legacy,
+            gravity: Vector3::new(0.0, -1.0, 0.0),
+            timestep: 1.0 / 60.0,
+            terminal_velocity: f32::INFINITY
+        }
+    }
+}
+
+// How an entity responds to forces and collisions (PhysicsModel::Standard only)
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalProperties {
+    // Must be positive. Infinite mass makes an entity immovable by other entities
+    pub mass: f32,
+    // The fraction of velocity lost per second
+    pub drag: f32,
+    // Scaled by the friction of the tile, and slows movement along its surface
+    pub friction: f32,
+    // The fraction of velocity retained when bouncing off of an obstacle
+    pub restitution: f32
+}
+
+impl Default for PhysicalProperties {
+    fn default() -> Self {
+        Self {
+            mass: 1.0,
+            drag: 0.0,
+            friction: 1.0,
+            restitution: 0.0
+        }
+    }
+}
+
+// Whatever stopped an entity from moving
+#[derive(Clone, Copy)]
+enum Obstacle {
+    Tile(Point3<i16>),
+    // The index of the entity's Body
+    Entity(usize)
+}
+
+// The state of each entity that other entities collide with
+struct Body {
+    bounds: Aabb,
+    layers: u32,
+    mask: u32
+}
+
+impl Body {
+    // Both bodies must accept each other's layers
+    fn collides_with(&self, other: &Self) -> bool {
+        self.layers & other.mask != 0 && other.layers & self.mask != 0
+    }
+}
+
+impl<'a> World<'a> {
+    pub fn physics_config(&self) -> &PhysicsConfig {
+        &self.physics_config
+    }
+
+    pub fn set_physics_config(&mut self, physics_config: PhysicsConfig) {
+        self.physics_config = physics_config;
+    }
+
+    // Contact events that weren't drained since the previous tick are discarded.
+    // Nothing is simulated while the World is paused
+    pub(crate) fn resolve_entity_physics(&mut self) {
+        self.contact_events.clear();
+
+        if self.is_paused() { return; }
+
+        // Bodies are indexed in the same order as the handles
+        let handles = self.entity_objects.handles().collect::<Vec<_>>();
+        let mut bodies = self.entity_objects
+            .iter()
+            .map(|(handle, entity)| {
+                // Attached entities don't block anything, they're moved by their parent
+                let layers = match self.attachments.contains(handle) {
+                    true => 0,
+                    false => entity.collision_layers()
+                };
+
+                Body {
+                    bounds: entity.bounds(),
+                    layers,
+                    mask: entity.collision_mask()
+                }
+            } )
+            .collect::<Vec<_>>();
+
+        let mut broadphase = SpatialHash::default();
+        for (index, body) in bodies.iter().enumerate() {
+            broadphase.insert(index, &body.bounds);
+        }
+
+        // Each pair of entities is only reported once
+        let mut touching = BTreeSet::new();
+
+        for index in 0..handles.len() {
+            if self.attachments.contains(handles[index]) {
+                continue;
+            }
+
+            let hits = match self.physics_config.model {
+                PhysicsModel::Standard => self.step_entity(&handles, index, &mut bodies, &mut broadphase),
+                PhysicsModel::Legacy => self.step_entity_legacy(&handles, index, &mut bodies, &mut broadphase)
+            };
+
+            let mut collisions = Vector3::new(false, false, false);
+            for (axis, hit, normal) in hits {
+                match hit {
+                    Obstacle::Tile(position) => {
+                        self.contact_events.push(ContactEvent::Tile {
+                            entity: handles[index],
+                            position,
+                            normal
+                        } );
+                    },
+                    Obstacle::Entity(other) => {
+                        touching.insert((index.min(other), index.max(other)));
+                    }
+                }
+
+                collisions[axis] = true;
+            }
+
+            if let Some(entity) = self.entity_mut(handles[index]) {
+                entity.set_collisions(collisions);
+            }
+        }
+
+        // Entities can still overlap, either because they were placed that way
+        // or because their layers don't collide
+        for (index, body) in bodies.iter().enumerate() {
+            for other in broadphase.query(&body.bounds).into_iter().filter(|other| *other > index) {
+                // Children usually overlap their parents
+                let attached = self.parent(handles[index]) == Some(handles[other])
+                    || self.parent(handles[other]) == Some(handles[index]);
+
+                if !attached && body.bounds.intersects(&bodies[other].bounds) {
+                    touching.insert((index, other));
+                }
+            }
+        }
+
+        for (index, other) in touching {
+            self.contact_events.push(ContactEvent::Entity {
+                entity: handles[index],
+                other: handles[other]
+            } );
+        }
+
+        // Kept for spatial queries, until an entity is added, removed or modified (e.g. by a trigger callback)
+        *self.entity_broadphase.get_mut() = Some(EntityBroadphase { hash: broadphase, handles: handles.clone() } );
+
+        self.resolve_triggers(&handles, &bodies);
+    }
+
+    // Callbacks are run after each tick, once for every entity that is inside of
+    // (or just left) a trigger tile. They are free to modify the World
+    pub fn add_trigger_callback<F>(&mut self, callback: F)
+        where F: FnMut(&mut World<'a>, &TriggerEvent) + 'a {
+
+        self.trigger_callbacks.push(Box::new(callback));
+    }
+
+    fn resolve_triggers(&mut self, handles: &[EntityHandle], bodies: &[Body]) {
+        let mut occupants = BTreeSet::new();
+        for (entity, body) in handles.iter().zip(bodies.iter()) {
+            for position in body.bounds.tiles() {
+                if matches!(self.get_tile(position).map(|tile| tile.collision()), Some(TileCollision::Trigger)) {
+                    occupants.insert((*entity, [position.x, position.y, position.z]));
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        for key @ (entity, position) in occupants.iter() {
+            let phase = match self.trigger_occupants.contains(key) {
+                true => TriggerPhase::Stay,
+                false => TriggerPhase::Enter
+            };
+
+            events.push(TriggerEvent { phase, entity: *entity, position: (*position).into() });
+        }
+
+        for key @ (entity, position) in self.trigger_occupants.iter() {
+            if !occupants.contains(key) {
+                events.push(TriggerEvent { phase: TriggerPhase::Exit, entity: *entity, position: (*position).into() });
+            }
+        }
+
+        self.trigger_occupants = occupants;
+
+        self.run_trigger_callbacks(events);
+    }
+
+    // Produces an Exit event for every trigger tile the entity occupies, see World::despawn
+    pub(crate) fn exit_triggers(&mut self, entity: EntityHandle) {
+        let occupied = self.trigger_occupants
+            .iter()
+            .filter(|(occupant, ..)| *occupant == entity)
+            .copied()
+            .collect::<Vec<_>>();
+
+        // The entity's events that haven't been dispatched yet are dropped
+        self.trigger_queue.retain(|event| event.entity != entity);
+
+        let mut events = Vec::new();
+        for key @ (entity, position) in occupied {
+            self.trigger_occupants.remove(&key);
+            events.push(TriggerEvent { phase: TriggerPhase::Exit, entity, position: position.into() });
+        }
+
+        self.run_trigger_callbacks(events);
+    }
+
+    // Events raised by a callback (e.g. the Exit events of an entity it despawns)
+    // are dispatched once the current event has been handled
+    fn run_trigger_callbacks(&mut self, events: Vec<TriggerEvent>) {
+        self.trigger_queue.extend(events);
+        if self.dispatching_triggers { return; }
+
+        self.dispatching_triggers = true;
+        while let Some(event) = self.trigger_queue.pop_front() {
+            // The callbacks are moved out of the World so that they can be given access to it
+            let mut callbacks = mem::take(&mut self.trigger_callbacks);
+            for callback in callbacks.iter_mut() {
+                callback(self, &event);
+            }
+
+            // Callbacks may have added more callbacks
+            callbacks.append(&mut self.trigger_callbacks);
+            self.trigger_callbacks = callbacks;
+        }
+
+        self.dispatching_triggers = false;
+    }
+
+    // Removes all contact events produced by the most recent tick
+    pub fn drain_contact_events(&mut self) -> impl Iterator<Item = ContactEvent> + '_ {
+        self.contact_events.drain(..)
+    }
+
+    // Integrates the entity's velocity over a single timestep, then bounces it off of
+    // whatever it hit. Returns each obstacle along with its axis and normal
+    fn step_entity(
+        &mut self,
+        handles: &[EntityHandle],
+        index: usize,
+        bodies: &mut [Body],
+        broadphase: &mut SpatialHash
+    ) -> Vec<(usize, Obstacle, Vector3<f32>)> {
+        let config = self.physics_config;
+        let timestep = self.delta_time();
+
+        let (mut velocity, properties) = match self.entity(handles[index]) {
+            Some(entity) => (entity.velocity(), entity.physical_properties()),
+            None => return Vec::new()
+        };
+
+        // Liquids add to the entity's own drag while it is submerged
+        let liquid_drag = bodies[index].bounds
+            .tiles()
+            .filter_map(|position| match self.get_tile(position)?.collision() {
+                TileCollision::Liquid { drag } => Some(drag),
+                _ => None
+            } )
+            .fold(0.0, f32::max);
+
+        velocity += config.gravity * timestep;
+        velocity *= (1.0 - (properties.drag + liquid_drag) * timestep).max(0.0);
+        if velocity.magnitude2() > config.terminal_velocity * config.terminal_velocity {
+            velocity = velocity.normalize_to(config.terminal_velocity);
+        }
+
+        let displacement = velocity * timestep;
+        let hits = self.move_body(handles, index, displacement, bodies, broadphase);
+
+        // Impacts slower than a couple of ticks worth of gravity don't bounce,
+        // otherwise resting entities would never settle
+        let threshold = config.gravity.magnitude() * timestep * 2.0;
+
+        let mut contacts = Vec::new();
+        for (axis, hit) in hits.into_iter().enumerate() {
+            let hit = match hit {
+                Some(hit) => hit,
+                None => continue
+            };
+
+            let mut normal = Vector3::zero();
+            normal[axis] = -displacement[axis].signum();
+
+            let impact = velocity[axis];
+            match hit {
+                Obstacle::Tile(position) => {
+                    let bounce = -impact * properties.restitution;
+                    velocity[axis] = if bounce.abs() > threshold { bounce } else { 0.0 };
+
+                    // The harder the entity is pressed against the surface, the more it is slowed
+                    let friction = self.get_tile(position).map_or(1.0, |tile| tile.friction());
+                    let mut tangent = velocity;
+                    tangent[axis] = 0.0;
+
+                    let speed = tangent.magnitude();
+                    if speed > 0.0 {
+                        let slowed = (speed - properties.friction * friction * impact.abs()).max(0.0);
+                        velocity += tangent * (slowed / speed - 1.0);
+                    }
+                },
+                Obstacle::Entity(other) => {
+                    let other_entity = match self.entity_mut(handles[other]) {
+                        Some(other_entity) => other_entity,
+                        None => continue
+                    };
+
+                    let other_properties = other_entity.physical_properties();
+                    let mut other_velocity = other_entity.velocity();
+
+                    // Exchanges momentum along the axis of the collision
+                    let inverse_mass = properties.mass.recip();
+                    let other_inverse_mass = other_properties.mass.recip();
+                    if inverse_mass + other_inverse_mass > 0.0 {
+                        let restitution = properties.restitution.max(other_properties.restitution);
+                        let impulse = -(1.0 + restitution) * (impact - other_velocity[axis]) 
+                            / (inverse_mass + other_inverse_mass);
+
+                        velocity[axis] += impulse * inverse_mass;
+                        other_velocity[axis] -= impulse * other_inverse_mass;
+                        other_entity.set_velocity(other_velocity);
+                    }
+                }
+            }
+
+            contacts.push((axis, hit, normal));
+        }
+
+        if let Some(entity) = self.entity_mut(handles[index]) {
+            entity.set_velocity(velocity);
+        }
+
+        contacts
+    }
+
+    // Moves the entity by its velocity, then by its weight (see PhysicsModel::Legacy)
+    fn step_entity_legacy(
+        &mut self,
+        handles: &[EntityHandle],
+        index: usize,
+        bodies: &mut [Body],
+        broadphase: &mut SpatialHash
+    ) -> Vec<(usize, Obstacle, Vector3<f32>)> {
+        let (velocity, weight) = match self.entity(handles[index]) {
+            Some(entity) => (entity.velocity(), entity.weight()),
+            None => return Vec::new()
+        };
+
+        let gravity = self.physics_config.gravity * weight;
+        let time_scale = self.time_scale();
+
+        let mut contacts = Vec::new();
+        for displacement in [velocity, gravity] {
+            let hits = self.move_body(handles, index, displacement * time_scale, bodies, broadphase);
+
+            let entity = match self.entity_mut(handles[index]) {
+                Some(entity) => entity,
+                None => break
+            };
+
+            let velocity = entity.velocity();
+
+            let mut diff = Vector3::new(0.0, 0.0, 0.0);
+            for (axis, hit) in hits.into_iter().enumerate() {
+                if let Some(hit) = hit {
+                    diff[axis] = displacement[axis];
+
+                    let mut normal = Vector3::zero();
+                    normal[axis] = -displacement[axis].signum();
+                    contacts.push((axis, hit, normal));
+                }
+            }
+
+            entity.set_velocity((velocity - diff) * (1.0 - weight * time_scale));
+        }
+
+        contacts
+    }
+
+    // Moves the entity as far as it can go, returning whatever blocked it along each axis
+    fn move_body(
+        &mut self,
+        handles: &[EntityHandle],
+        entity_index: usize,
+        displacement: Vector3<f32>,
+        bodies: &mut [Body],
+        broadphase: &mut SpatialHash
+    ) -> [Option<Obstacle>; 3] {
+        let bounds = bodies[entity_index].bounds;
+
+        // The entities that could be hit anywhere along the path
+        let path = Aabb::new(
+            Point3::new(
+                bounds.min.x.min(bounds.min.x + displacement.x),
+                bounds.min.y.min(bounds.min.y + displacement.y),
+                bounds.min.z.min(bounds.min.z + displacement.z)
+            ),
+            Point3::new(
+                bounds.max.x.max(bounds.max.x + displacement.x),
+                bounds.max.y.max(bounds.max.y + displacement.y),
+                bounds.max.z.max(bounds.max.z + displacement.z)
+            )
+        );
+
+        let obstacles = broadphase
+            .query(&path)
+            .into_iter()
+            .filter(|other| *other != entity_index)
+            .filter(|other| bodies[entity_index].collides_with(&bodies[*other]))
+            .map(|other| (bodies[other].bounds, Obstacle::Entity(other)))
+            .collect::<Vec<_>>();
+
+        let (actual_displacement, hits) = self.sweep(bounds, displacement, &obstacles);
+
+        if !actual_displacement.is_zero() {
+            if let Some(entity) = self.entity_mut(handles[entity_index]) {
+                let center = entity.center();
+                entity.set_center(center + actual_displacement);
+            }
+
+            let moved = bounds.translate(actual_displacement);
+
+            broadphase.remove(entity_index, &bounds);
+            broadphase.insert(entity_index, &moved);
+            bodies[entity_index].bounds = moved;
+        }
+
+        hits
+    }
+
+    // Moves the box through the World until it is blocked by a tile or one of the obstacles,
+    // one axis at a time. Returns the distance traveled, along with what blocked the box along each axis
+    fn sweep(
+        &self,
+        mut bounds: Aabb,
+        displacement: Vector3<f32>,
+        obstacles: &[(Aabb, Obstacle)]
+    ) -> (Vector3<f32>, [Option<Obstacle>; 3]) {
+        // Resolving each axis separately can cut across corners,
+        // so large displacements are split into several smaller steps
+        let largest = displacement.x.abs().max(displacement.y.abs()).max(displacement.z.abs());
+        let steps = (largest / SWEEP_STEP).ceil().max(1.0) as u32;
+        let step = displacement / steps as f32;
+
+        let mut traveled = Vector3::zero();
+        let mut hits = [None; 3];
+        for _ in 0..steps {
+            // Vertical movement is resolved first, so entities slide along the ground
+            for axis in [1, 0, 2] {
+                if hits[axis].is_some() { continue; }
+
+                let (distance, hit) = self.sweep_axis(&bounds, axis, step[axis], obstacles);
+
+                bounds.min[axis] += distance;
+                bounds.max[axis] += distance;
+                traveled[axis] += distance;
+                hits[axis] = hit;
+            }
+        }
+
+        (traveled, hits)
+    }
+
+    // Returns how far the box can move along the axis, and what stopped it (if anything)
+    fn sweep_axis(
+        &self,
+        bounds: &Aabb,
+        axis: usize,
+        distance: f32,
+        obstacles: &[(Aabb, Obstacle)]
+    ) -> (f32, Option<Obstacle>) {
+        if distance == 0.0 { return (0.0, None); }
+
+        // The region the box passes through. Obstacles that only touch the sides of the box are ignored
+        let mut swept = *bounds;
+        for other in (0..3).filter(|other| *other != axis) {
+            swept.min[other] += SWEEP_EPSILON;
+            swept.max[other] -= SWEEP_EPSILON;
+        }
+
+        if distance > 0.0 {
+            swept.min[axis] = bounds.max[axis];
+            swept.max[axis] = bounds.max[axis] + distance;
+        } else {
+            swept.min[axis] = bounds.min[axis] + distance;
+            swept.max[axis] = bounds.min[axis];
+        }
+
+        // One-way platforms only stop entities that are falling
+        let tiles = swept
+            .tiles()
+            .filter(|position| match self.get_tile(*position).map(|tile| tile.collision()) {
+                Some(TileCollision::Solid) => true,
+                Some(TileCollision::OneWay) => axis == 1 && distance < 0.0,
+                _ => false
+            } )
+            .map(|position| (Aabb::from_tile(position), Obstacle::Tile(position)));
+
+        tiles
+            .chain(obstacles.iter().copied())
+            .filter(|(obstacle, ..)| obstacle.intersects(&swept))
+            // Obstacles the box already overlaps don't block it, otherwise it could never escape them
+            .filter_map(|(obstacle, hit)| if distance > 0.0 {
+                (obstacle.min[axis] >= bounds.max[axis] - SWEEP_EPSILON)
+                    .then(|| ((obstacle.min[axis] - bounds.max[axis]).max(0.0), hit))
+            } else {
+                (obstacle.max[axis] <= bounds.min[axis] + SWEEP_EPSILON)
+                    .then(|| ((obstacle.max[axis] - bounds.min[axis]).min(0.0), hit))
+            } )
+            .fold((distance, None), |(allowed, blocked), (limit, hit)| {
+                if limit.abs() < allowed.abs() { (limit, Some(hit)) } else { (allowed, blocked) }
+            } )
+    }
+
+    // Every entity whose bounding box intersects the given box
+    pub fn entities_in_aabb(&self, bounds: &Aabb) -> Vec<EntityHandle> {
+        self.entities_near(bounds, |entity_bounds| entity_bounds.intersects(bounds))
+    }
+
+    // Every entity whose bounding box is within the radius of the given point
+    pub fn entities_in_radius(&self, center: Point3<f32>, radius: f32) -> Vec<EntityHandle> {
+        let extents = Vector3::new(radius, radius, radius);
+
+        self.entities_near(&Aabb::from_center(center, extents), |entity_bounds| {
+            entity_bounds.closest_point(center).distance2(center) <= radius * radius
+        } )
+    }
+
+    // The entities in the broadphase cells that the bounds overlap, which pass the test.
+    // The broadphase is rebuilt if the entities have changed since the last physics step
+    fn entities_near<F>(&self, bounds: &Aabb, test: F) -> Vec<EntityHandle>
+        where F: Fn(&Aabb) -> bool {
+
+        let mut broadphase = self.entity_broadphase.borrow_mut();
+        let broadphase = broadphase.get_or_insert_with(|| {
+            let mut hash = SpatialHash::default();
+            let handles = self.entity_objects.handles().collect::<Vec<_>>();
+            for (index, (.., entity)) in self.entity_objects.iter().enumerate() {
+                hash.insert(index, &entity.bounds());
+            }
+
+            EntityBroadphase { hash, handles }
+        } );
+
+        // The bounds moved by the physics step can differ slightly from those of the entities
+        let margin = Vector3::new(SWEEP_EPSILON, SWEEP_EPSILON, SWEEP_EPSILON);
+        let bounds = Aabb::new(bounds.min - margin, bounds.max + margin);
+
+        broadphase
+            .query(&bounds)
+            .filter(|handle| self.entity(*handle).is_some_and(|entity| test(&entity.bounds())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ cell::RefCell, rc::Rc };
+
+    use cgmath::{ Point3, Vector3, Zero };
+
+    use crate::world::{
+        testing::{ Ball, Block },
+        Aabb,
+        ContactEvent,
+        PhysicalProperties,
+        PhysicsConfig,
+        TileCollision,
+        TriggerPhase,
+        World
+    };
+
+    // A World without gravity
+    fn world<'a>() -> World<'a> {
+        let mut world = World::default();
+        world.set_physics_config(PhysicsConfig { gravity: Vector3::zero(), ..PhysicsConfig::default() } );
+        world
+    }
+
+    fn tick(world: &mut World) {
+        world.advance_clock();
+        world.resolve_entity_physics();
+    }
+
+    type Recorded = Rc<RefCell<Vec<(TriggerPhase, [i16; 3])>>>;
+
+    // Records the phase and tile position of every trigger event
+    fn record(world: &mut World) -> Recorded {
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        let recorded = events.clone();
+        world.add_trigger_callback(move |_, event| {
+            recorded.borrow_mut().push((event.phase, event.position.into()));
+        } );
+
+        events
+    }
+
+    #[test]
+    fn trigger_enter_stay_exit() {
+        let mut world = world();
+        world.add_tile(Block::at(0, 0, 0).with_collision(TileCollision::Trigger));
+        let events = record(&mut world);
+
+        let ball = world.add_entity(Ball::at(0.0, 0.0, 0.0), None);
+        tick(&mut world);
+        tick(&mut world);
+
+        world.entity_mut(ball).unwrap().set_center(Point3::new(5.0, 0.0, 0.0));
+        tick(&mut world);
+        tick(&mut world);
+
+        assert_eq!(*events.borrow(), [
+            (TriggerPhase::Enter, [0, 0, 0]),
+            (TriggerPhase::Stay, [0, 0, 0]),
+            (TriggerPhase::Exit, [0, 0, 0])
+        ] );
+    }
+
+    #[test]
+    fn despawned_occupant_exits_while_alive() {
+        let mut world = world();
+        world.add_tile(Block::at(0, 0, 0).with_collision(TileCollision::Trigger));
+
+        let alive = Rc::new(RefCell::new(Vec::new()));
+        let recorded = alive.clone();
+        world.add_trigger_callback(move |world, event| {
+            recorded.borrow_mut().push((event.phase, world.is_alive(event.entity)));
+        } );
+
+        let ball = world.add_entity(Ball::at(0.0, 0.0, 0.0), None);
+        tick(&mut world);
+        world.despawn(ball);
+        tick(&mut world);
+
+        assert_eq!(*alive.borrow(), [(TriggerPhase::Enter, true), (TriggerPhase::Exit, true)]);
+    }
+
+    #[test]
+    fn despawn_from_trigger_callback_exits() {
+        let mut world = world();
+        world.add_tile(Block::at(0, 0, 0).with_collision(TileCollision::Trigger));
+        world.add_tile(Block::at(1, 0, 0).with_collision(TileCollision::Trigger));
+        let events = record(&mut world);
+
+        world.add_trigger_callback(|world, event| {
+            if event.phase == TriggerPhase::Enter {
+                world.despawn(event.entity);
+            }
+        } );
+
+        // Overlaps both triggers, the second Enter is dropped once the entity is despawned
+        let ball = world.add_entity(Ball::at(0.5, 0.0, 0.0), None);
+        tick(&mut world);
+        tick(&mut world);
+
+        assert!(!world.is_alive(ball));
+        assert_eq!(*events.borrow(), [
+            (TriggerPhase::Enter, [0, 0, 0]),
+            (TriggerPhase::Exit, [0, 0, 0]),
+            (TriggerPhase::Exit, [1, 0, 0])
+        ] );
+    }
+
+    #[test]
+    fn legacy_moves_in_tiles_per_tick() {
+        for timestep in [1.0 / 60.0, 1.0] {
+            let mut world = World::default();
+            world.set_physics_config(PhysicsConfig { timestep, ..PhysicsConfig::legacy() } );
+
+            // Moved by its velocity, then by its weight, damping the velocity after each move
+            let falling = world.add_entity(Ball { velocity: [1.0, 0.0, 0.0], weight: 0.5, ..Ball::at(0.0, 0.0, 0.0) }, None);
+            // Without weight, the velocity is kept however fast it is
+            let drifting = world.add_entity(Ball { velocity: [0.0, 0.0, 100.0], ..Ball::at(0.0, 10.0, 0.0) }, None);
+            tick(&mut world);
+
+            let falling = world.entity(falling).unwrap();
+            assert_eq!(falling.center(), Point3::new(1.0, -0.5, 0.0));
+            assert_eq!(falling.velocity(), Vector3::new(0.25, 0.0, 0.0));
+
+            let drifting = world.entity(drifting).unwrap();
+            assert_eq!(drifting.center(), Point3::new(0.0, 10.0, 100.0));
+            assert_eq!(drifting.velocity(), Vector3::new(0.0, 0.0, 100.0));
+        }
+    }
+
+    #[test]
+    fn legacy_collisions_are_swept() {
+        let mut world = World::default();
+        world.set_physics_config(PhysicsConfig::legacy());
+        world.add_tile(Block::at(5, 0, 0));
+
+        let ball = world.add_entity(Ball { velocity: [20.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        tick(&mut world);
+
+        let ball = world.entity(ball).unwrap();
+        assert!((ball.center().x - 4.25).abs() < 1e-3, "stopped at {:?}", ball.center());
+        assert_eq!(ball.velocity(), Vector3::zero());
+        assert_eq!(ball.collisions(), Vector3::new(true, false, false));
+    }
+
+    #[test]
+    fn fast_entities_do_not_tunnel() {
+        let mut world = World::default();
+        world.set_physics_config(PhysicsConfig { terminal_velocity: f32::INFINITY, ..PhysicsConfig::default() } );
+        world.add_tile(Block::at(10, 0, 0));
+        world.add_tile(Block::at(0, -10, 5));
+
+        // 100 tiles per tick
+        let sideways = world.add_entity(Ball { velocity: [6000.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        let falling = world.add_entity(Ball { velocity: [0.0, -6000.0, 0.0], ..Ball::at(0.0, 0.0, 5.0) }, None);
+        tick(&mut world);
+
+        let sideways = world.entity(sideways).unwrap();
+        assert!((sideways.center().x - 9.25).abs() < 1e-3, "stopped at {:?}", sideways.center());
+        assert_eq!(sideways.velocity().x, 0.0);
+
+        let falling = world.entity(falling).unwrap();
+        assert!((falling.center().y + 9.25).abs() < 1e-3, "stopped at {:?}", falling.center());
+        assert_eq!(falling.velocity().y, 0.0);
+    }
+
+    #[test]
+    fn sweeps_stop_at_the_nearest_obstacle() {
+        let mut world = world();
+        world.set_physics_config(PhysicsConfig { terminal_velocity: f32::INFINITY, ..*world.physics_config() } );
+        world.add_tile(Block::at(3, 0, 0));
+        world.add_tile(Block::at(6, 0, 0));
+
+        // 10 tiles per tick
+        let ball = world.add_entity(Ball { velocity: [600.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        tick(&mut world);
+
+        assert!((world.entity(ball).unwrap().center().x - 2.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn landing_reports_collisions_and_contacts() {
+        let mut world = World::default();
+        world.add_tile(Block::at(0, 0, 0));
+
+        let ball = world.add_entity(Ball::at(0.0, 0.75, 0.0), None);
+        tick(&mut world);
+
+        let entity = world.entity(ball).unwrap();
+        assert_eq!(entity.collisions(), Vector3::new(false, true, false));
+        assert!((entity.center().y - 0.75).abs() < 1e-3);
+
+        let contacts = world.drain_contact_events().collect::<Vec<_>>();
+        assert!(matches!(
+            contacts[..],
+            [ContactEvent::Tile { entity, position, normal }]
+                if entity == ball && position == Point3::new(0, 0, 0) && normal == Vector3::unit_y()
+        ), "{:?}", contacts);
+
+        // Drained events aren't reported again, and collisions are cleared once the entity is free
+        assert_eq!(world.drain_contact_events().count(), 0);
+        world.entity_mut(ball).unwrap().set_center(Point3::new(0.0, 5.0, 0.0));
+        tick(&mut world);
+        assert_eq!(world.entity(ball).unwrap().collisions(), Vector3::new(false, false, false));
+        assert_eq!(world.drain_contact_events().count(), 0);
+    }
+
+    #[test]
+    fn entity_contacts_are_reported_once_per_pair() {
+        let mut world = world();
+
+        let a = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        let b = world.add_entity(Ball { velocity: [-30.0, 0.0, 0.0], ..Ball::at(0.6, 0.0, 0.0) }, None);
+        // Overlapping, but on layers that don't collide
+        let c = world.add_entity(Ball { layers: 2, mask: 2, ..Ball::at(0.0, 5.0, 0.0) }, None);
+        let d = world.add_entity(Ball { layers: 4, mask: 4, ..Ball::at(0.1, 5.0, 0.0) }, None);
+        tick(&mut world);
+
+        let mut pairs = world.drain_contact_events()
+            .map(|event| match event {
+                ContactEvent::Entity { entity, other } => (entity, other),
+                ContactEvent::Tile { .. } => panic!("unexpected {:?}", event)
+            } )
+            .collect::<Vec<_>>();
+        pairs.sort();
+
+        assert_eq!(pairs, [(a, b), (c, d)]);
+        assert_eq!(world.entity(a).unwrap().collisions(), Vector3::new(true, false, false));
+        assert_eq!(world.entity(c).unwrap().collisions(), Vector3::new(false, false, false));
+    }
+
+    #[test]
+    fn entities_block_each_other_unless_their_layers_differ() {
+        let mut world = world();
+        let immovable = PhysicalProperties { mass: f32::INFINITY, ..PhysicalProperties::default() };
+        let wall = world.add_entity(Ball { properties: immovable, ..Ball::at(2.0, 0.0, 0.0) }, None);
+        let blocked = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        let ghost = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], layers: 2, mask: 2, ..Ball::at(0.0, 0.0, 0.0) }, None);
+
+        for _ in 0..4 {
+            tick(&mut world);
+        }
+
+        assert!((world.entity(blocked).unwrap().center().x - 1.5).abs() < 1e-3);
+        assert_eq!(world.entity(ghost).unwrap().center().x, 2.0);
+        assert_eq!(world.entity(wall).unwrap().center().x, 2.0);
+    }
+
+    #[test]
+    fn spatial_queries() {
+        let mut world = world();
+        let near = world.add_entity(Ball::at(1.0, 0.0, 0.0), None);
+        let far = world.add_entity(Ball::at(10.0, 0.0, 0.0), None);
+        let diagonal = world.add_entity(Ball::at(2.0, 2.0, 0.0), None);
+        tick(&mut world);
+
+        let mut found = world.entities_in_aabb(&Aabb::new(Point3::new(0.0, -1.0, -1.0), Point3::new(2.0, 2.0, 1.0)));
+        found.sort();
+        assert_eq!(found, [near, diagonal]);
+
+        // The corner of the diagonal entity's box is about 2.47 away
+        assert_eq!(world.entities_in_radius(Point3::new(0.0, 0.0, 0.0), 2.4), [near]);
+        let mut found = world.entities_in_radius(Point3::new(0.0, 0.0, 0.0), 2.5);
+        found.sort();
+        assert_eq!(found, [near, diagonal]);
+
+        assert_eq!(world.entities_in_radius(Point3::new(10.0, 0.0, 0.0), 0.5), [far]);
+    }
+
+    #[test]
+    fn spatial_queries_see_changes_since_the_last_tick() {
+        let mut world = world();
+        let moved = world.add_entity(Ball::at(0.0, 0.0, 0.0), None);
+        let removed = world.add_entity(Ball::at(20.0, 0.0, 0.0), None);
+        tick(&mut world);
+
+        let everywhere = Aabb::new(Point3::new(-50.0, -50.0, -50.0), Point3::new(50.0, 50.0, 50.0));
+        assert_eq!(world.entities_in_aabb(&everywhere).len(), 2);
+
+        world.entity_mut(moved).unwrap().set_center(Point3::new(-30.0, 0.0, 0.0));
+        world.despawn(removed);
+        let added = world.add_entity(Ball::at(30.0, 0.0, 0.0), None);
+
+        assert_eq!(world.entities_in_radius(Point3::new(-30.0, 0.0, 0.0), 1.0), [moved]);
+        assert!(world.entities_in_radius(Point3::new(0.0, 0.0, 0.0), 1.0).is_empty());
+        assert!(world.entities_in_radius(Point3::new(20.0, 0.0, 0.0), 1.0).is_empty());
+        assert_eq!(world.entities_in_radius(Point3::new(30.0, 0.0, 0.0), 1.0), [added]);
+    }
+
+    #[test]
+    fn one_way_tiles_only_block_falling_entities() {
+        let mut world = world();
+        for x in [0, 5, 10] {
+            world.add_tile(Block::at(x, 0, 0).with_collision(TileCollision::OneWay));
+        }
+
+        let falling = world.add_entity(Ball { velocity: [0.0, -30.0, 0.0], ..Ball::at(0.0, 2.0, 0.0) }, None);
+        let rising = world.add_entity(Ball { velocity: [0.0, 30.0, 0.0], ..Ball::at(5.0, -2.0, 0.0) }, None);
+        let sideways = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(8.0, 0.0, 0.0) }, None);
+
+        for _ in 0..8 {
+            tick(&mut world);
+        }
+
+        assert!((world.entity(falling).unwrap().center().y - 0.75).abs() < 1e-3);
+        assert_eq!(world.entity(rising).unwrap().center().y, 2.0);
+        assert_eq!(world.entity(sideways).unwrap().center().x, 12.0);
+    }
+
+    #[test]
+    fn one_way_tiles_release_entities_inside_of_them() {
+        let mut world = world();
+        world.add_tile(Block::at(0, 0, 0).with_collision(TileCollision::OneWay));
+
+        // Having jumped halfway through, it falls back out of the bottom
+        let ball = world.add_entity(Ball { velocity: [0.0, -30.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        for _ in 0..4 {
+            tick(&mut world);
+        }
+
+        assert_eq!(world.entity(ball).unwrap().center().y, -2.0);
+    }
+
+    #[test]
+    fn passable_and_liquid_tiles_do_not_block() {
+        let mut world = world();
+        world.add_tile(Block::at(2, 0, 0).with_collision(TileCollision::Passable));
+        world.add_tile(Block::at(2, 5, 0).with_collision(TileCollision::Liquid { drag: 6.0 } ));
+
+        let passing = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        let wading = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(0.0, 5.0, 0.0) }, None);
+        for _ in 0..8 {
+            tick(&mut world);
+        }
+
+        assert_eq!(world.entity(passing).unwrap().center().x, 4.0);
+
+        // Slowed while submerged, but not stopped
+        let wading = world.entity(wading).unwrap();
+        assert!(wading.center().x > 2.0 && wading.center().x < 4.0, "{:?}", wading.center());
+        assert!(wading.velocity().x < 30.0);
+    }
+}
@@ -0,0 +1,319 @@
+use cgmath::{
+    InnerSpace,
+    Point3,
+    Vector3,
+    Zero
+};
+
+use super::{
+    EntityHandle,
+    TileCollision,
+    World
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct TileHit {
+    pub position: Point3<i16>,
+    // Points away from the face that was hit.
+    // Zero if the ray started inside of the tile
+    pub normal: Vector3<f32>,
+    pub point: Point3<f32>,
+    pub distance: f32
+}
+
+impl TileHit {
+    // Where a tile would be placed against the face that was hit
+    pub fn adjacent(&self) -> Point3<i16> {
+        self.position + self.normal.cast::<i16>().unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EntityHit {
+    pub entity: EntityHandle,
+    // Zero if the ray started inside of the entity's bounding box
+    pub normal: Vector3<f32>,
+    pub point: Point3<f32>,
+    pub distance: f32
+}
+
+// A ray that crosses the whole World steps through at most this many cells
+const MAX_RAY_STEPS: usize = 3 * (u16::MAX as usize + 1);
+
+impl<'a> World<'a> {
+    // Finds the first tile along the ray, using a voxel traversal (Amanatides & Woo).
+    // Passable tiles are ignored, and rays with a zero or non-finite direction never hit
+    pub fn raycast(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32
+    ) -> Option<TileHit> {
+        // Zero directions normalize to NaN
+        let direction = direction.normalize();
+        let coordinates: [[f32; 3]; 2] = [direction.into(), origin.into()];
+        if !coordinates.iter().flatten().all(|c| c.is_finite()) || max_distance.is_nan() {
+            return None;
+        }
+
+        // Tiles are centered on their positions, shifting the origin aligns them with the grid
+        let start = origin;
+        let origin = origin + Vector3::new(0.5, 0.5, 0.5);
+
+        // Tiles can't exist outside of the range of i16, so the ray is clipped to it
+        let (enter, exit, enter_axis) = clip_to_world(origin, direction)?;
+        let max_distance = max_distance.min(exit);
+
+        let mut distance = enter.max(0.0);
+        let mut normal = Vector3::zero();
+        if let Some(axis) = enter_axis {
+            normal[axis] = -direction[axis].signum();
+        }
+
+        let entry = origin + direction * distance;
+        let mut cell = [0, 1, 2].map(|axis| {
+            (entry[axis].floor() as i32).clamp(i16::MIN as i32, i16::MAX as i32)
+        } );
+
+        let mut step = [0; 3];
+        let mut next = [f32::INFINITY; 3];
+        let mut delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let direction = direction[axis];
+
+            if direction > 0.0 {
+                step[axis] = 1;
+                next[axis] = distance + (cell[axis] as f32 + 1.0 - entry[axis]) / direction;
+            } else if direction < 0.0 {
+                step[axis] = -1;
+                next[axis] = distance + (entry[axis] - cell[axis] as f32) / -direction;
+            }
+
+            if direction != 0.0 {
+                delta[axis] = direction.abs().recip();
+            }
+        }
+
+        for _ in 0..MAX_RAY_STEPS {
+            if distance > max_distance {
+                break;
+            }
+
+            // The ray has left the World
+            let position = Point3::new(
+                i16::try_from(cell[0]).ok()?,
+                i16::try_from(cell[1]).ok()?,
+                i16::try_from(cell[2]).ok()?
+            );
+
+            let hit = self.get_tile(position)
+                .map(|tile| tile.collision() != TileCollision::Passable)
+                .unwrap_or(false);
+
+            if hit {
+                return Some(TileHit {
+                    position,
+                    normal,
+                    point: start + direction * distance,
+                    distance
+                } );
+            }
+
+            // Step into the neighboring cell whose boundary is closest
+            let axis = if next[0] < next[1] && next[0] < next[2] {
+                0
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+
+            distance = next[axis];
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+
+            normal = Vector3::zero();
+            normal[axis] = -step[axis] as f32;
+        }
+
+        None
+    }
+
+    // Finds the closest entity whose bounding box is hit by the ray.
+    // Only entities with one of the given collision layers are tested
+    pub fn raycast_entities(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+        layers: u32
+    ) -> Option<EntityHit> {
+        let direction = direction.normalize();
+
+        self.entity_objects
+            .iter()
+            .filter_map(|(handle, entity)| {
+                if entity.collision_layers() & layers == 0 {
+                    return None;
+                }
+
+                let (distance, normal) = entity.bounds().intersect_ray(origin, direction)?;
+
+                (distance <= max_distance).then(|| EntityHit {
+                    entity: handle,
+                    normal,
+                    point: origin + direction * distance,
+                    distance
+                } )
+            } )
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+// Where the ray enters and exits the grid of cells that tiles can occupy, and the axis it enters
+// through (None if it starts inside). The origin must already be aligned with the grid
+fn clip_to_world(origin: Point3<f32>, direction: Vector3<f32>) -> Option<(f32, f32, Option<usize>)> {
+    let (min, max) = (i16::MIN as f32, i16::MAX as f32 + 1.0);
+
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut enter_axis = None;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < min || origin[axis] >= max {
+                return None;
+            }
+
+            continue;
+        }
+
+        let a = (min - origin[axis]) / direction[axis];
+        let b = (max - origin[axis]) / direction[axis];
+        let (near, far) = (a.min(b), a.max(b));
+
+        if near > enter {
+            enter = near;
+            enter_axis = Some(axis);
+        }
+        exit = exit.min(far);
+    }
+
+    if enter > exit || exit < 0.0 {
+        return None;
+    }
+
+    Some((enter, exit, enter_axis.filter(|_| enter > 0.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ Point3, Vector3 };
+
+    use crate::world::{
+        testing::Block,
+        TileCollision,
+        World
+    };
+
+    fn world<'a>(blocks: impl IntoIterator<Item = Block>) -> World<'a> {
+        let mut world = World::default();
+        for block in blocks {
+            world.add_tile(block);
+        }
+        world
+    }
+
+    #[test]
+    fn hits_the_first_solid_tile() {
+        let world = world([
+            Block::at(2, 0, 0).with_collision(TileCollision::Passable),
+            Block::at(3, 0, 0),
+            Block::at(4, 0, 0)
+        ] );
+
+        let hit = world.raycast(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.position, Point3::new(3, 0, 0));
+        assert_eq!(hit.distance, 2.5);
+        assert_eq!(hit.point, Point3::new(2.5, 0.0, 0.0));
+        assert_eq!(hit.adjacent(), Point3::new(2, 0, 0));
+    }
+
+    #[test]
+    fn misses() {
+        let world = world([Block::at(3, 0, 0)]);
+
+        assert!(world.raycast(Point3::new(0.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0), 10.0).is_none());
+        assert!(world.raycast(Point3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 10.0).is_none());
+    }
+
+    #[test]
+    fn stops_at_max_distance() {
+        let world = world([Block::at(3, 0, 0)]);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let direction = Vector3::new(1.0, 0.0, 0.0);
+
+        assert!(world.raycast(origin, direction, 2.4).is_none());
+        assert!(world.raycast(origin, direction, 2.5).is_some());
+        assert!(world.raycast(origin, direction, f32::INFINITY).is_some());
+    }
+
+    #[test]
+    fn normals_point_out_of_the_face_that_was_hit() {
+        let world = world([Block::at(0, 0, 0)]);
+        let faces = [
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)),
+            (Vector3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+            (Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+            (Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+            (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0)),
+            (Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 0.0, 1.0))
+        ];
+
+        for (normal, direction) in faces {
+            let hit = world.raycast(Point3::new(0.0, 0.0, 0.0) + normal * 3.0, direction, 10.0).unwrap();
+            assert_eq!(hit.normal, normal);
+            assert_eq!(hit.distance, 2.5);
+        }
+
+        let inside = world.raycast(Point3::new(0.1, 0.2, 0.0), Vector3::new(1.0, 1.0, 0.0), 10.0).unwrap();
+        assert_eq!(inside.normal, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(inside.distance, 0.0);
+    }
+
+    #[test]
+    fn invalid_directions_never_hit() {
+        let world = world([Block::at(0, 0, 0)]);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        for direction in [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(f32::NAN, 0.0, 0.0),
+            Vector3::new(f32::INFINITY, 0.0, 0.0)
+        ] {
+            assert!(world.raycast(origin, direction, 10.0).is_none());
+        }
+    }
+
+    #[test]
+    fn rays_from_outside_the_world_are_clipped() {
+        let world = world([Block::at(i16::MAX, 0, 0), Block::at(i16::MIN, 5, 0)]);
+
+        let hit = world.raycast(Point3::new(40000.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0), f32::INFINITY).unwrap();
+        assert_eq!(hit.position, Point3::new(i16::MAX, 0, 0));
+        assert_eq!(hit.normal, Vector3::new(1.0, 0.0, 0.0));
+
+        let hit = world.raycast(Point3::new(-40000.0, 5.0, 0.0), Vector3::new(1.0, 0.0, 0.0), f32::INFINITY).unwrap();
+        assert_eq!(hit.position, Point3::new(i16::MIN, 5, 0));
+        assert_eq!(hit.normal, Vector3::new(-1.0, 0.0, 0.0));
+
+        assert!(world.raycast(Point3::new(40000.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), f32::INFINITY).is_none());
+        assert!(world.raycast(Point3::new(0.0, 40000.0, 0.0), Vector3::new(1.0, 0.0, 0.0), f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn empty_worlds_end_the_ray() {
+        let world = world([]);
+
+        assert!(world.raycast(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 2.0, 3.0), f32::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{ self, Read },
+    time
+};
+
+use cgmath::{ Point3, Vector3 };
+
+use crate::camera;
+
+use super::{ Entity, Tile, World };
+
+// Identifies world files, followed by the format version
+const MAGIC: &[u8; 4] = b"BEWF";
+const VERSION: u32 = 1;
+
+// Tiles and entities are stored as trait objects, so the World needs to know
+// how to construct each implementor when it is loaded.
+// Every type is registered under a stable name, which is what ends up on disk
+#[derive(Default)]
+pub struct Registry {
+    tiles: HashMap<String, fn() -> Box<dyn Tile>>,
+    entities: HashMap<String, fn() -> Box<dyn Entity>>,
+    // Maps the type name of each registered type (see Drawable::type_name) to its registered name
+    names: HashMap<&'static str, String>
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Loaded tiles are constructed with Default, then their position, color and light are set
+    pub fn register_tile<T: Tile + Default + 'static>(&mut self, name: &str) {
+        fn construct<T: Tile + Default + 'static>() -> Box<dyn Tile> {
+            Box::new(T::default())
+        }
+
+        self.tiles.insert(name.to_owned(), construct::<T>);
+        self.names.insert(T::default().type_name(), name.to_owned());
+    }
+
+    // Loaded entities are constructed with Default, then their center, color, light,
+    // velocity and weight are set
+    pub fn register_entity<T: Entity + Default + 'static>(&mut self, name: &str) {
+        fn construct<T: Entity + Default + 'static>() -> Box<dyn Entity> {
+            Box::new(T::default())
+        }
+
+        self.entities.insert(name.to_owned(), construct::<T>);
+        self.names.insert(T::default().type_name(), name.to_owned());
+    }
+
+    fn name(&self, type_name: &'static str) -> io::Result<&str> {
+        self.names.get(type_name).map(|name| name.as_str()).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has not been registered", type_name)
+        ))
+    }
+}
+
+impl<'a> World<'a> {
+    // Writes all tiles and entities, along with the state of the camera.
+    // Every tile and entity type in the World must be registered.
+    // Components, systems, attachments and lights added with add_light aren't saved
+    pub fn save<W: io::Write>(
+        &self,
+        camera: &camera::Camera,
+        registry: &Registry,
+        mut writer: W
+    ) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, VERSION)?;
+
+        write_f32s(&mut writer, &[camera.distance, camera.pitch, camera.yaw])?;
+        write_f32s(&mut writer, &[camera.target.x, camera.target.y, camera.target.z])?;
+        write_camera_mode(&mut writer, camera.mode)?;
+        write_f32s(&mut writer, &[camera.roll])?;
+
+        write_u32(&mut writer, self.tiles().count() as u32)?;
+        for tile in self.tiles() {
+            write_str(&mut writer, registry.name(tile.type_name())?)?;
+
+            let position = tile.position();
+            for coordinate in [position.x, position.y, position.z] {
+                writer.write_all(&coordinate.to_le_bytes())?;
+            }
+
+            write_f32s(&mut writer, &tile.color())?;
+            write_option(&mut writer, tile.light(), |writer, light| write_f32s(writer, &light))?;
+        }
+
+        write_u32(&mut writer, self.entity_objects.len() as u32)?;
+        for (handle, entity) in self.entity_objects.iter() {
+            write_str(&mut writer, registry.name(entity.type_name())?)?;
+
+            let tags = self.tags(handle).collect::<Vec<_>>();
+            write_u32(&mut writer, tags.len() as u32)?;
+            for tag in tags {
+                write_str(&mut writer, tag)?;
+            }
+
+            let (center, velocity) = (entity.center(), entity.velocity());
+            write_f32s(&mut writer, &[center.x, center.y, center.z])?;
+            write_f32s(&mut writer, &[velocity.x, velocity.y, velocity.z])?;
+            write_f32s(&mut writer, &[entity.weight()])?;
+            write_f32s(&mut writer, &entity.color())?;
+            write_option(&mut writer, entity.light(), |writer, light| write_f32s(writer, &light))?;
+
+            // Only the remaining portion of the lifetime is stored
+            let remaining = self.entity_lifetimes
+                .get(handle)
+                .map(|expiry| (expiry - self.elapsed()).max(0.0));
+            write_option(&mut writer, remaining, |writer, remaining| {
+                writer.write_all(&remaining.to_le_bytes())
+            } )?;
+        }
+
+        Ok(())
+    }
+
+    // Replaces the contents of the World with those of a saved world, and restores the camera.
+    // On failure, neither the World nor the camera is modified
+    pub fn load<R: io::Read>(
+        &mut self,
+        camera: &mut camera::Camera,
+        registry: &Registry,
+        mut reader: R
+    ) -> io::Result<()> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a world file".to_owned()));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported world version {}", version)));
+        }
+
+        let [distance, pitch, yaw] = read_f32s(&mut reader)?;
+        let target: [f32; 3] = read_f32s(&mut reader)?;
+        let mode = read_camera_mode(&mut reader)?;
+        let [roll] = read_f32s(&mut reader)?;
+
+        // The physics configuration, clock (and trigger callbacks) aren't part of the saved world.
+        // Handles are still allocated by the same Allocator, so existing handles become stale
+        let mut allocator = self.allocator.clone();
+        allocator.free_all();
+
+        let mut world = World { 
+            physics_config: self.physics_config, 
+            clock: self.clock,
+            allocator, 
+            ..World::default() 
+        };
+
+        for _ in 0..read_u32(&mut reader)? {
+            let name = read_str(&mut reader)?;
+            let construct = registry.tiles.get(&name).ok_or_else(|| {
+                invalid_data(format!("unknown tile type {}", name))
+            } )?;
+
+            let mut position = [0; 3];
+            for coordinate in position.iter_mut() {
+                let mut bytes = [0; 2];
+                reader.read_exact(&mut bytes)?;
+                *coordinate = i16::from_le_bytes(bytes);
+            }
+
+            let mut tile = construct();
+            tile.set_position(position.into());
+            tile.set_color(read_f32s(&mut reader)?);
+            if let Some(light) = read_option(&mut reader, read_f32s)? {
+                tile.set_light(light);
+            }
+
+            world.insert_tile(tile);
+        }
+
+        for _ in 0..read_u32(&mut reader)? {
+            let name = read_str(&mut reader)?;
+            let construct = registry.entities.get(&name).ok_or_else(|| {
+                invalid_data(format!("unknown entity type {}", name))
+            } )?;
+
+            let tags = (0..read_u32(&mut reader)?)
+                .map(|_| read_str(&mut reader))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            let mut entity = construct();
+
+            let center: [f32; 3] = read_f32s(&mut reader)?;
+            let velocity: [f32; 3] = read_f32s(&mut reader)?;
+            let [weight] = read_f32s(&mut reader)?;
+
+            entity.set_center(Point3::from(center));
+            entity.set_velocity(Vector3::from(velocity));
+            entity.set_weight(weight);
+            entity.set_color(read_f32s(&mut reader)?);
+            if let Some(light) = read_option(&mut reader, read_f32s)? {
+                entity.set_light(light);
+            }
+
+            let lifetime = read_option(&mut reader, |reader| {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+
+                time::Duration::try_from_secs_f64(f64::from_le_bytes(bytes))
+                    .map_err(|e| invalid_data(e.to_string()))
+            } )?;
+
+            let handle = world.insert_entity(entity, lifetime);
+            for tag in tags {
+                world.entity_tags.insert(Cow::Owned(tag), handle);
+            }
+        }
+
+        // Callbacks and systems are kept, but entities that occupied triggers are forgotten.
+        // The replaced entities are dropped without running the despawn callbacks
+        world.trigger_callbacks = std::mem::take(&mut self.trigger_callbacks);
+        world.despawn_callbacks = std::mem::take(&mut self.despawn_callbacks);
+        world.systems = std::mem::take(&mut self.systems);
+        *self = world;
+
+        camera.mode = mode;
+        camera.distance = distance;
+        camera.pitch = pitch;
+        camera.yaw = yaw;
+        camera.roll = roll;
+        camera.target = target.into();
+        camera.update();
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32<W: io::Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u32<R: io::Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_f32s<W: io::Write>(writer: &mut W, values: &[f32]) -> io::Result<()> {
+    values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn read_f32s<R: io::Read, const N: usize>(reader: &mut R) -> io::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *value = f32::from_le_bytes(bytes);
+    }
+
+    Ok(values)
+}
+
+// Strings are prefixed with their length in bytes
+fn write_str<W: io::Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_str<R: io::Read>(reader: &mut R) -> io::Result<String> {
+    let length = read_u32(reader)? as usize;
+
+    let mut bytes = Vec::new();
+    reader.by_ref().take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    String::from_utf8(bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+// The mode is stored as a single byte, LookAt is followed by its eye
+fn write_camera_mode<W: io::Write>(writer: &mut W, mode: camera::CameraMode) -> io::Result<()> {
+    match mode {
+        camera::CameraMode::Orbit => writer.write_all(&[0]),
+        camera::CameraMode::FirstPerson => writer.write_all(&[1]),
+        camera::CameraMode::FreeFly => writer.write_all(&[2]),
+        camera::CameraMode::LookAt { eye } => {
+            writer.write_all(&[3])?;
+            write_f32s(writer, &[eye.x, eye.y, eye.z])
+        }
+    }
+}
+
+fn read_camera_mode<R: io::Read>(reader: &mut R) -> io::Result<camera::CameraMode> {
+    let mut mode = [0];
+    reader.read_exact(&mut mode)?;
+
+    match mode[0] {
+        0 => Ok(camera::CameraMode::Orbit),
+        1 => Ok(camera::CameraMode::FirstPerson),
+        2 => Ok(camera::CameraMode::FreeFly),
+        3 => {
+            let eye: [f32; 3] = read_f32s(reader)?;
+            Ok(camera::CameraMode::LookAt { eye: eye.into() } )
+        },
+        _ => Err(invalid_data("unknown camera mode".to_owned()))
+    }
+}
+
+// Optional values are prefixed with a single byte, indicating whether they are present
+fn write_option<W: io::Write, T, F>(writer: &mut W, value: Option<T>, write: F) -> io::Result<()>
+    where F: FnOnce(&mut W, T) -> io::Result<()> {
+
+    match value {
+        Some(value) => {
+            writer.write_all(&[1])?;
+            write(writer, value)
+        },
+        None => writer.write_all(&[0])
+    }
+}
+
+fn read_option<R: io::Read, T, F>(reader: &mut R, read: F) -> io::Result<Option<T>>
+    where F: FnOnce(&mut R) -> io::Result<T> {
+
+    let mut present = [0];
+    reader.read_exact(&mut present)?;
+
+    match present[0] {
+        0 => Ok(None),
+        1 => read(reader).map(Some),
+        _ => Err(invalid_data("malformed optional value".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ io, time };
+
+    use cgmath::{ Point3, Vector3 };
+
+    use crate::camera;
+
+    use crate::world::testing::{ Ball, Block };
+
+    use super::{ Registry, World };
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register_tile::<Block>("block");
+        registry.register_entity::<Ball>("ball");
+        registry
+    }
+
+    // A tile, and two entities with tags, lights and lifetimes
+    fn saved_world(camera: &camera::Camera) -> Vec<u8> {
+        let mut world = World::default();
+        world.add_tile(Block { color: [0.1, 0.2, 0.3], ..Block::at(1, -2, 3) } );
+        world.add_tile(Block { light: Some([1.0, 0.5, 0.0, 2.0]), ..Block::at(-4, 5, 6) } );
+
+        let ball = Ball {
+            center: [0.5, 1.5, -2.5],
+            velocity: [1.0, 0.0, -1.0],
+            weight: 0.25,
+            color: [0.0, 1.0, 0.0],
+            light: Some([0.0, 0.0, 1.0, 1.0]),
+            ..Ball::default()
+        };
+
+        let handle = world.add_entity_with_tag("player", ball, Some(time::Duration::from_secs(3)));
+        world.tag_entity("hero", handle);
+        world.add_entity(Ball::default(), None);
+
+        let mut bytes = Vec::new();
+        world.save(camera, &registry(), &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let saved_camera = camera::CameraBuilder::new()
+            .mode(camera::CameraMode::LookAt { eye: Point3::new(8.0, 4.0, 2.0) } )
+            .target(Point3::new(1.0, 2.0, 3.0))
+            .distance(7.0)
+            .pitch(0.5)
+            .yaw(-1.0)
+            .roll(0.25)
+            .build();
+
+        let bytes = saved_world(&saved_camera);
+
+        let mut world = World::default();
+        let mut camera = camera::Camera::default();
+        world.load(&mut camera, &registry(), bytes.as_slice()).unwrap();
+
+        assert_eq!(camera.mode(), saved_camera.mode());
+        assert_eq!(camera.target, saved_camera.target);
+        assert_eq!((camera.distance, camera.pitch, camera.yaw, camera.roll), (7.0, 0.5, -1.0, 0.25));
+
+        assert_eq!(world.tiles().count(), 2);
+        let tile = world.get_tile(Point3::new(-4, 5, 6)).unwrap();
+        assert_eq!(tile.color(), [1.0; 3]);
+        assert_eq!(tile.light(), Some([1.0, 0.5, 0.0, 2.0]));
+        assert_eq!(world.get_tile(Point3::new(1, -2, 3)).unwrap().light(), None);
+
+        assert_eq!(world.entities().count(), 2);
+        let handle = world.get_entity("player").unwrap();
+        assert_eq!(world.get_entity("hero"), Some(handle));
+
+        let entity = world.entity(handle).unwrap();
+        assert_eq!(entity.center(), Point3::new(0.5, 1.5, -2.5));
+        assert_eq!(entity.velocity(), Vector3::new(1.0, 0.0, -1.0));
+        assert_eq!(entity.weight(), 0.25);
+        assert_eq!(entity.color(), [0.0, 1.0, 0.0]);
+        assert_eq!(entity.light(), Some([0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(world.entity_lifetimes.get(handle), Some(&3.0));
+        assert_eq!(world.entity_lifetimes.len(), 1);
+    }
+
+    #[test]
+    fn truncated_input_leaves_world_unchanged() {
+        let bytes = saved_world(&camera::Camera::default());
+
+        let mut world = World::default();
+        world.add_tile(Block::default());
+        let mut camera = camera::Camera::default();
+
+        for length in 0..bytes.len() {
+            let error = world.load(&mut camera, &registry(), &bytes[..length]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "truncated to {} bytes", length);
+        }
+
+        assert_eq!(world.tiles().count(), 1);
+        assert!(world.contains_tile(&Point3::new(0, 0, 0)));
+        assert_eq!(camera.distance, camera::Camera::default().distance);
+    }
+
+    #[test]
+    fn corrupt_input_is_rejected() {
+        let bytes = saved_world(&camera::Camera::default());
+        let load = |bytes: &[u8]| {
+            World::default().load(&mut camera::Camera::default(), &registry(), bytes).unwrap_err().kind()
+        };
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert_eq!(load(&magic), io::ErrorKind::InvalidData);
+
+        for version in [0, 2, u32::MAX] {
+            let mut unsupported = bytes.clone();
+            unsupported[4..8].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(load(&unsupported), io::ErrorKind::InvalidData);
+        }
+
+        // The camera mode follows the magic, version and 6 floats
+        let mut mode = bytes.clone();
+        mode[32] = 9;
+        assert_eq!(load(&mode), io::ErrorKind::InvalidData);
+
+        // Types that aren't registered can't be constructed
+        let error = World::default()
+            .load(&mut camera::Camera::default(), &Registry::new(), bytes.as_slice())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,254 @@
+use cgmath::{ One, Point3, Quaternion, Rotation, Vector3 };
+
+use super::{ EntityHandle, World };
+
+// Where a child is placed relative to its parent, see World::attach
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment {
+    pub parent: EntityHandle,
+    // Measured in the parent's space, so it turns along with the parent
+    pub offset: Vector3<f32>,
+    pub rotation: Quaternion<f32>
+}
+
+impl<'a> World<'a> {
+    // A light that doesn't belong to a tile or entity, e.g. one that orbits an entity (see attach).
+    // It's removed with World::despawn
+    pub fn add_light(&mut self, center: Point3<f32>, light: [f32; 4]) -> EntityHandle {
+        let handle = self.spawn();
+        self.lights.insert(handle, (center, light));
+
+        handle
+    }
+
+    // Returns false if the handle wasn't created by add_light
+    pub fn set_light(&mut self, handle: EntityHandle, light: [f32; 4]) -> bool {
+        match self.lights.get_mut(handle) {
+            Some((.., current)) => {
+                *current = light;
+                true
+            },
+            None => false
+        }
+    }
+
+    // The center of the entity or light
+    pub fn position(&self, handle: EntityHandle) -> Option<Point3<f32>> {
+        match self.entity(handle) {
+            Some(entity) => Some(entity.center()),
+            None => self.lights.get(handle).map(|(center, ..)| *center)
+        }
+    }
+
+    fn set_position(&mut self, handle: EntityHandle, position: Point3<f32>) {
+        if let Some(entity) = self.entity_mut(handle) {
+            entity.set_center(position);
+        } else if let Some((center, ..)) = self.lights.get_mut(handle) {
+            *center = position;
+        }
+    }
+
+    // The rotation the entity is drawn with, which includes the rotations of its parents
+    pub fn rotation(&self, handle: EntityHandle) -> Quaternion<f32> {
+        self.frame(handle).1
+    }
+
+    // Moves the child (an entity or light) along with its parent from now on.
+    // Attached entities aren't simulated, and don't block or get blocked by other entities.
+    // Children are despawned along with their parent.
+    // Returns false if either one has been despawned, or if the parent is attached to the child
+    pub fn attach(
+        &mut self,
+        child: EntityHandle,
+        parent: EntityHandle,
+        offset: Vector3<f32>,
+        rotation: Quaternion<f32>
+    ) -> bool {
+        if self.position(child).is_none() || self.position(parent).is_none() {
+            return false;
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(handle) = ancestor {
+            if handle == child {
+                return false;
+            }
+
+            ancestor = self.parent(handle);
+        }
+
+        self.attachments.insert(child, Attachment { parent, offset, rotation });
+
+        // The child jumps to its parent, instead of being interpolated there
+        let (position, ..) = self.frame(child);
+        self.set_position(child, position);
+        self.previous_centers.remove(&child);
+
+        true
+    }
+
+    // The child stays where it is
+    pub fn detach(&mut self, child: EntityHandle) -> Option<Attachment> {
+        self.attachments.remove(child)
+    }
+
+    // Changes where the child is placed relative to its parent, starting with the next tick
+    pub fn set_attachment_offset(
+        &mut self,
+        child: EntityHandle,
+        offset: Vector3<f32>,
+        rotation: Quaternion<f32>
+    ) -> bool {
+        match self.attachments.get_mut(child) {
+            Some(attachment) => {
+                attachment.offset = offset;
+                attachment.rotation = rotation;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn attachment(&self, child: EntityHandle) -> Option<Attachment> {
+        self.attachments.get(child).copied()
+    }
+
+    pub fn parent(&self, child: EntityHandle) -> Option<EntityHandle> {
+        self.attachments.get(child).map(|attachment| attachment.parent)
+    }
+
+    pub fn children(&self, parent: EntityHandle) -> Vec<EntityHandle> {
+        self.attachments
+            .iter()
+            .filter(|(.., attachment)| attachment.parent == parent)
+            .map(|(child, ..)| child)
+            .collect()
+    }
+
+    // The position and rotation of the entity, after those of its parents are applied
+    fn frame(&self, handle: EntityHandle) -> (Point3<f32>, Quaternion<f32>) {
+        let rotation = self.entity(handle).map_or(Quaternion::one(), |entity| entity.rotation());
+
+        match self.attachments.get(handle) {
+            Some(attachment) => {
+                let (parent_position, parent_rotation) = self.frame(attachment.parent);
+
+                (
+                    parent_position + parent_rotation.rotate_vector(attachment.offset),
+                    parent_rotation * attachment.rotation * rotation
+                )
+            },
+            None => (self.position(handle).unwrap_or_else(|| Point3::new(0.0, 0.0, 0.0)), rotation)
+        }
+    }
+
+    // Moves every child to its parent, called after the physics step
+    pub(crate) fn resolve_attachments(&mut self) {
+        let children = self.attachments.handles().collect::<Vec<_>>();
+        for child in children {
+            let (position, ..) = self.frame(child);
+            self.set_position(child, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ Deg, InnerSpace, One, Point3, Quaternion, Rotation3, Vector3, Zero };
+
+    use crate::world::{ testing::Ball, PhysicsConfig, World };
+
+    fn world<'a>() -> World<'a> {
+        let mut world = World::default();
+        world.set_physics_config(PhysicsConfig { gravity: Vector3::zero(), ..PhysicsConfig::default() } );
+        world
+    }
+
+    fn tick(world: &mut World) {
+        world.advance_clock();
+        world.resolve_entity_physics();
+        world.resolve_attachments();
+    }
+
+    fn close(a: Point3<f32>, b: Point3<f32>) -> bool {
+        (a - b).magnitude2() < 1e-8
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut world = world();
+        let parent = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::at(0.0, 0.0, 0.0) }, None);
+        let child = world.add_entity(Ball { velocity: [0.0, 0.0, -30.0], ..Ball::at(5.0, 5.0, 5.0) }, None);
+        let light = world.add_light(Point3::new(0.0, 0.0, 0.0), [1.0; 4]);
+
+        assert!(world.attach(child, parent, Vector3::new(0.0, 1.0, 0.0), Quaternion::one()));
+        assert!(world.attach(light, child, Vector3::new(0.0, 0.0, 2.0), Quaternion::one()));
+        assert_eq!(world.position(child), Some(Point3::new(0.0, 1.0, 0.0)));
+        assert_eq!(world.children(parent), [child]);
+
+        // The child's own velocity is ignored
+        tick(&mut world);
+        assert_eq!(world.position(child), Some(Point3::new(0.5, 1.0, 0.0)));
+        assert_eq!(world.position(light), Some(Point3::new(0.5, 1.0, 2.0)));
+    }
+
+    #[test]
+    fn offsets_turn_with_the_parent() {
+        let mut world = world();
+        let parent = world.add_entity(Ball::default(), None);
+        let child = world.add_entity(Ball::default(), None);
+        let grandchild = world.add_entity(Ball::default(), None);
+
+        let quarter_turn = Quaternion::from_angle_y(Deg(90.0));
+        world.attach(child, parent, Vector3::new(1.0, 0.0, 0.0), quarter_turn);
+        world.attach(grandchild, child, Vector3::new(1.0, 0.0, 0.0), Quaternion::one());
+
+        assert!(close(world.position(grandchild).unwrap(), Point3::new(1.0, 0.0, -1.0)));
+        assert!((world.rotation(grandchild) - quarter_turn).magnitude2() < 1e-8);
+    }
+
+    #[test]
+    fn cycles_and_dead_entities_are_refused() {
+        let mut world = world();
+        let parent = world.add_entity(Ball::default(), None);
+        let child = world.add_entity(Ball::default(), None);
+        let dead = world.add_entity(Ball::default(), None);
+        world.despawn(dead);
+
+        assert!(world.attach(child, parent, Vector3::zero(), Quaternion::one()));
+        assert!(!world.attach(parent, child, Vector3::zero(), Quaternion::one()));
+        assert!(!world.attach(parent, parent, Vector3::zero(), Quaternion::one()));
+        assert!(!world.attach(dead, parent, Vector3::zero(), Quaternion::one()));
+        assert!(!world.attach(parent, dead, Vector3::zero(), Quaternion::one()));
+    }
+
+    #[test]
+    fn children_are_despawned_with_their_parent() {
+        let mut world = world();
+        let parent = world.add_entity(Ball::default(), None);
+        let child = world.add_entity(Ball::default(), None);
+        let grandchild = world.add_light(Point3::new(0.0, 0.0, 0.0), [1.0; 4]);
+        world.attach(child, parent, Vector3::zero(), Quaternion::one());
+        world.attach(grandchild, child, Vector3::zero(), Quaternion::one());
+
+        world.despawn(parent);
+
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+        assert!(world.position(grandchild).is_none());
+    }
+
+    #[test]
+    fn detached_children_stay_where_they_are() {
+        let mut world = world();
+        let parent = world.add_entity(Ball { velocity: [30.0, 0.0, 0.0], ..Ball::default() }, None);
+        let child = world.add_entity(Ball::default(), None);
+        world.attach(child, parent, Vector3::new(0.0, 3.0, 0.0), Quaternion::one());
+
+        assert_eq!(world.detach(child).map(|attachment| attachment.parent), Some(parent));
+        tick(&mut world);
+
+        assert_eq!(world.position(child), Some(Point3::new(0.0, 3.0, 0.0)));
+        assert_eq!(world.parent(child), None);
+    }
+}
@@ -0,0 +1,126 @@
+use cgmath::{ Point3, Vector3 };
+
+use super::{
+    Drawable,
+    Entity,
+    PhysicalProperties,
+    Tile,
+    TileCollision,
+    Triangles
+};
+
+// A tile for unit tests, an opaque cube unless it has a light or isn't solid
+pub(crate) struct Block {
+    pub(crate) position: [i16; 3],
+    pub(crate) color: [f32; 3],
+    pub(crate) light: Option<[f32; 4]>,
+    pub(crate) collision: TileCollision
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Self {
+            position: [0; 3],
+            color: [1.0; 3],
+            light: None,
+            collision: TileCollision::Solid
+        }
+    }
+}
+
+impl Block {
+    pub(crate) fn at(x: i16, y: i16, z: i16) -> Self {
+        Self { position: [x, y, z], ..Self::default() }
+    }
+
+    pub(crate) fn with_collision(mut self, collision: TileCollision) -> Self {
+        self.collision = collision;
+        self
+    }
+}
+
+impl Tile for Block {
+    fn position(&self) -> Point3<i16> { self.position.into() }
+    fn set_position(&mut self, position: Point3<i16>) { self.position = position.into(); }
+
+    fn is_opaque_cube(&self) -> bool { self.light.is_none() && self.collision == TileCollision::Solid }
+    fn collision(&self) -> TileCollision { self.collision }
+}
+
+impl Drawable for Block {
+    fn center(&self) -> Point3<f32> { Point3::from(self.position).cast::<f32>().unwrap() }
+    fn set_center(&mut self, center: Point3<f32>) { self.position = center.cast::<i16>().unwrap().into(); }
+
+    fn color(&self) -> [f32; 3] { self.color }
+    fn set_color(&mut self, color: [f32; 3]) { self.color = color; }
+
+    fn light(&self) -> Option<[f32; 4]> { self.light }
+    fn set_light(&mut self, light: [f32; 4]) { self.light = Some(light); }
+
+    fn build_object_data(&self) -> Triangles { Triangles::default() }
+}
+
+// An entity for unit tests, a box with a half-width of 0.25 by default
+pub(crate) struct Ball {
+    pub(crate) center: [f32; 3],
+    pub(crate) velocity: [f32; 3],
+    pub(crate) weight: f32,
+    pub(crate) color: [f32; 3],
+    pub(crate) light: Option<[f32; 4]>,
+    pub(crate) collisions: [bool; 3],
+    pub(crate) half_extent: f32,
+    pub(crate) properties: PhysicalProperties,
+    pub(crate) layers: u32,
+    pub(crate) mask: u32
+}
+
+impl Default for Ball {
+    fn default() -> Self {
+        Self {
+            center: [0.0; 3],
+            velocity: [0.0; 3],
+            weight: 0.0,
+            color: [1.0; 3],
+            light: None,
+            collisions: [false; 3],
+            half_extent: 0.25,
+            properties: PhysicalProperties::default(),
+            layers: 1,
+            mask: u32::MAX
+        }
+    }
+}
+
+impl Ball {
+    pub(crate) fn at(x: f32, y: f32, z: f32) -> Self {
+        Self { center: [x, y, z], ..Self::default() }
+    }
+}
+
+impl Entity for Ball {
+    fn velocity(&self) -> Vector3<f32> { self.velocity.into() }
+    fn collisions(&self) -> Vector3<bool> { self.collisions.into() }
+    fn weight(&self) -> f32 { self.weight }
+
+    fn set_velocity(&mut self, velocity: Vector3<f32>) { self.velocity = velocity.into(); }
+    fn set_collisions(&mut self, collisions: Vector3<bool>) { self.collisions = collisions.into(); }
+    fn set_weight(&mut self, weight: f32) { self.weight = weight; }
+
+    fn half_extents(&self) -> Vector3<f32> { Vector3::new(self.half_extent, self.half_extent, self.half_extent) }
+    fn physical_properties(&self) -> PhysicalProperties { self.properties }
+    fn collision_layers(&self) -> u32 { self.layers }
+    fn collision_mask(&self) -> u32 { self.mask }
+}
+
+impl Drawable for Ball {
+    fn center(&self) -> Point3<f32> { self.center.into() }
+    fn set_center(&mut self, center: Point3<f32>) { self.center = center.into(); }
+
+    fn color(&self) -> [f32; 3] { self.color }
+    fn set_color(&mut self, color: [f32; 3]) { self.color = color; }
+
+    fn light(&self) -> Option<[f32; 4]> { self.light }
+    fn set_light(&mut self, light: [f32; 4]) { self.light = Some(light); }
+
+    fn build_object_data(&self) -> Triangles { Triangles::default() }
+}
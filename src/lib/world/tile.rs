@@ -1,9 +1,121 @@
+use std::ops::{ Deref, DerefMut };
+
 use cgmath::Point3;
 
-use super::drawable;
+use super::{ drawable, chunk, World };
+
+// How entities interact with a tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileCollision {
+    Solid,
+    // Doesn't affect entities at all
+    Passable,
+    // Only blocks entities that fall onto its top face
+    OneWay,
+    // Doesn't block entities, but reports those inside of it (see World::add_trigger_callback)
+    Trigger,
+    // Slows the entities inside of it, drag is the fraction of velocity lost per second.
+    // Only applies to PhysicsModel::Standard, otherwise it is passable
+    Liquid { drag: f32 }
+}
 
 pub trait Tile: drawable::Drawable {
     fn position(&self) -> Point3<i16>;
     
     fn set_position(&mut self, position: Point3<i16>);
-}
\ No newline at end of file
+
+    // Opaque cubes fill their entire tile with a single color (see Drawable::color).
+    // Their geometry is generated by the World instead of build_object_data,
+    // which allows hidden faces to be culled and adjacent faces to be merged
+    fn is_opaque_cube(&self) -> bool { false }
+
+    // Scales the friction of entities sliding along the tile (PhysicsModel::Standard only).
+    // Values close to zero behave like ice
+    fn friction(&self) -> f32 { 1.0 }
+
+    fn collision(&self) -> TileCollision { TileCollision::Solid }
+}
+
+// A mutable reference to a tile within the World.
+// Keeps the World's tile map consistent if the tile is moved. Moving a tile onto
+// another tile is refused, the moved tile is put back at its original position
+pub struct TileMut<'w, 'a> {
+    pub(crate) world: &'w mut World<'a>,
+    pub(crate) position: Point3<i16>
+}
+
+impl<'w, 'a> Deref for TileMut<'w, 'a> {
+    type Target = dyn Tile;
+
+    fn deref(&self) -> &Self::Target {
+        self.world.chunks[&chunk::Chunk::coordinates(self.position)]
+            .tiles[&self.position]
+            .as_ref()
+    }
+}
+
+impl<'w, 'a> DerefMut for TileMut<'w, 'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.world.chunks
+            .get_mut(&chunk::Chunk::coordinates(self.position))
+            .and_then(|chunk| chunk.tiles.get_mut(&self.position))
+            .unwrap()
+            .as_mut()
+    }
+}
+
+impl<'w, 'a> Drop for TileMut<'w, 'a> {
+    fn drop(&mut self) {
+        let destination = self.deref().position();
+        if destination == self.position {
+            return;
+        }
+
+        if self.world.contains_tile(&destination) {
+            let position = self.position;
+            self.deref_mut().set_position(position);
+        } else if let Some(tile) = self.world.remove_tile(self.position) {
+            self.world.insert_tile(tile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use crate::world::{ testing::Block, World };
+
+    #[test]
+    fn moved_tiles_are_relocated() {
+        let mut world = World::default();
+        world.add_tile(Block::at(0, 0, 0));
+
+        world.get_tile_mut(Point3::new(0, 0, 0)).unwrap().set_position(Point3::new(-20, 3, 0));
+
+        assert!(!world.contains_tile(&Point3::new(0, 0, 0)));
+        assert_eq!(world.get_tile(Point3::new(-20, 3, 0)).unwrap().position(), Point3::new(-20, 3, 0));
+        assert_eq!(world.tiles().count(), 1);
+    }
+
+    #[test]
+    fn moving_onto_another_tile_is_refused() {
+        let mut world = World::default();
+        world.add_tile(Block::at(0, 0, 0));
+        world.add_tile(Block { color: [0.0; 3], ..Block::at(1, 0, 0) } );
+
+        {
+            let mut tile = world.get_tile_mut(Point3::new(0, 0, 0)).unwrap();
+            tile.set_position(Point3::new(1, 0, 0));
+            tile.set_color([0.5; 3]);
+        }
+
+        // Other changes are kept
+        let tile = world.get_tile(Point3::new(0, 0, 0)).unwrap();
+        assert_eq!(tile.position(), Point3::new(0, 0, 0));
+        assert_eq!(tile.color(), [0.5; 3]);
+
+        assert_eq!(world.get_tile(Point3::new(1, 0, 0)).unwrap().color(), [0.0; 3]);
+        assert_eq!(world.tiles().count(), 2);
+    }
+}